@@ -0,0 +1,130 @@
+use super::computations::Computations;
+use super::intersection::Intersection;
+use super::random::Rng;
+use super::ray::Ray;
+use super::sampling::cosine_hemisphere;
+use super::shape::CommonShape;
+use super::world::World;
+
+/// Estimates how much of the sky above a point is hidden by nearby geometry,
+/// by casting `samples` rays over the hemisphere around the surface normal.
+/// Only objects closer than `max_distance` occlude.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AmbientOcclusion {
+    pub samples: usize,
+    pub max_distance: f64,
+    pub seed: u64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(samples: usize, max_distance: f64) -> Self {
+        Self { samples, max_distance, seed: 0 }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+
+        self
+    }
+
+    /// The fraction of the hemisphere that is open, weighted by the cosine
+    /// from the normal; 1 for an unoccluded point and 0 for a closed one.
+    pub fn accessibility(&self, world: &World, computations: &Computations) -> f64 {
+        if self.samples == 0 {
+            return 1.;
+        }
+
+        let point = computations.over_point;
+        let mut rng = Rng::from_values(&[self.seed, point.x.to_bits(), point.y.to_bits(), point.z.to_bits()]);
+        let mut open = 0;
+        for _ in 0..self.samples {
+            let direction = cosine_hemisphere(computations.normal_vector, rng.next_f64(), rng.next_f64());
+            let ray = Ray::new(point, direction);
+
+            let occluded = Intersection::hit(world.intersect_world(ray)).is_some_and(|hit| {
+                hit.t < self.max_distance && hit.object.get_casts_shadow()
+            });
+            if !occluded {
+                open += 1;
+            }
+        }
+
+        open as f64 / self.samples as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::plane::Plane;
+    use super::super::shape::Shape;
+    use super::super::sphere::Sphere;
+    use super::super::transformation::*;
+    use super::super::tuple::Tuple;
+
+    fn floor_computations(world: &World) -> Computations {
+        let ray = Ray::new(Tuple::point(0., 0.25, 0.), Tuple::vector(0., -1., 0.));
+        let intersections = world.intersect_world(ray);
+
+        Intersection::hit(intersections.clone()).unwrap().prepare_computations(ray, intersections)
+    }
+
+    #[test]
+    fn open_surface_is_fully_accessible() {
+        let mut world = World::new();
+        world.objects.push(Shape::Plane(Plane::new()));
+        let ambient_occlusion = AmbientOcclusion::new(16, 10.);
+
+        let expected = 1.;
+
+        let actual = ambient_occlusion.accessibility(&world, &floor_computations(&world));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn surface_under_ceiling_is_fully_occluded() {
+        let mut world = World::new();
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_transform(translate(0., 0.5, 0.));
+        world.objects.push(ceiling);
+        let ambient_occlusion = AmbientOcclusion::new(16, 1000.);
+
+        let expected = 0.;
+
+        let actual = ambient_occlusion.accessibility(&world, &floor_computations(&world));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn occluders_beyond_max_distance_are_ignored() {
+        let mut world = World::new();
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_transform(translate(0., 5., 0.));
+        world.objects.push(ceiling);
+        let ambient_occlusion = AmbientOcclusion::new(16, 1.);
+
+        let expected = 1.;
+
+        let actual = ambient_occlusion.accessibility(&world, &floor_computations(&world));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn nearby_object_partly_occludes() {
+        let mut world = World::new();
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ball = Shape::Sphere(Sphere::new());
+        ball.set_transform(translate(1.5, 1., 0.));
+        world.objects.push(ball);
+        let ambient_occlusion = AmbientOcclusion::new(64, 10.);
+
+        let actual = ambient_occlusion.accessibility(&world, &floor_computations(&world));
+
+        assert!(actual > 0. && actual < 1.);
+    }
+}
@@ -0,0 +1,327 @@
+use super::BLACK;
+use super::canvas::Canvas;
+use super::color::Color;
+use super::tuple::Tuple;
+use std::f64::consts::PI;
+
+/// What a ray sees when it misses every object in the world.
+#[derive(Debug, Clone)]
+pub enum Background {
+    Color (Color),
+    Gradient (GradientSky),
+    Environment (EnvironmentMap),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(BLACK)
+    }
+}
+
+impl Background {
+    pub fn color_in(&self, direction: Tuple) -> Color {
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient(gradient) => gradient.color_in(direction),
+            Background::Environment(environment) => environment.color_in(direction),
+        }
+    }
+}
+
+/// A sky that blends from `horizon` up to `zenith`, and from `horizon` down
+/// to `ground` below it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GradientSky {
+    pub zenith: Color,
+    pub horizon: Color,
+    pub ground: Color,
+}
+
+impl GradientSky {
+    pub fn new(zenith: Color, horizon: Color, ground: Color) -> Self {
+        Self { zenith, horizon, ground }
+    }
+
+    pub fn color_in(&self, direction: Tuple) -> Color {
+        let height = direction.normalize().y;
+        if height >= 0. {
+            self.horizon + (self.zenith - self.horizon) * height
+        } else {
+            self.horizon + (self.ground - self.horizon) * -height
+        }
+    }
+}
+
+/// An equirectangular (latitude/longitude) image surrounding the scene. The
+/// center of the image lies in the -z direction and its top row is straight up,
+/// matching the camera's equirectangular projection.
+///
+/// When `samples` is greater than zero the map also lights diffuse surfaces,
+/// using that many shadow rays per point, sampled in proportion to the
+/// brightness of the map.
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    pub image: Canvas,
+    pub intensity: f64,
+    pub samples: usize,
+    pub seed: u64,
+    row_cdf: Vec<f64>,
+    column_cdfs: Vec<Vec<f64>>,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Canvas) -> Self {
+        let width = *image.get_width();
+        let height = *image.get_height();
+
+        // Rows near the poles cover less of the sphere, so weight each pixel
+        // by the sine of its polar angle.
+        let mut column_cdfs = Vec::with_capacity(height);
+        let mut row_weights = Vec::with_capacity(height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            let weights = (0..width).map(|x| {
+                luminance(image.pixel_at(x as u32, y as u32)).max(0.) * sin_theta
+            }).collect::<Vec<f64>>();
+
+            row_weights.push(weights.iter().sum());
+            column_cdfs.push(cumulative(&weights));
+        }
+
+        let row_cdf = cumulative(&row_weights);
+
+        Self { image, intensity: 1., samples: 0, seed: 0, row_cdf, column_cdfs }
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+
+        self
+    }
+
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+
+        self
+    }
+
+    /// Maps a direction to a position in the image, both in [0, 1].
+    pub fn direction_to_uv(direction: Tuple) -> (f64, f64) {
+        let direction = direction.normalize();
+        let longitude = (-direction.x).atan2(-direction.z);
+        let latitude = direction.y.clamp(-1., 1.).asin();
+
+        (longitude / (2. * PI) + 0.5, 0.5 - latitude / PI)
+    }
+
+    pub fn uv_to_direction(u: f64, v: f64) -> Tuple {
+        let longitude = (u - 0.5) * 2. * PI;
+        let latitude = (0.5 - v) * PI;
+
+        Tuple::vector(-longitude.sin() * latitude.cos(), latitude.sin(), -longitude.cos() * latitude.cos())
+    }
+
+    pub fn color_in(&self, direction: Tuple) -> Color {
+        let (x, y) = self.pixel_for(direction);
+
+        self.image.pixel_at(x as u32, y as u32) * self.intensity
+    }
+
+    /// Picks a direction in proportion to the brightness of the map, given two
+    /// numbers in [0, 1). Returns the direction and its probability density
+    /// with respect to solid angle.
+    pub fn sample(&self, u1: f64, u2: f64) -> Option<(Tuple, f64)> {
+        let width = *self.image.get_width();
+        let height = *self.image.get_height();
+        if width == 0 || height == 0 || *self.row_cdf.last().unwrap() <= 0. {
+            return None;
+        }
+
+        let (y, v) = sample_cdf(&self.row_cdf, u1);
+        let (_, u) = sample_cdf(&self.column_cdfs[y], u2);
+
+        let direction = Self::uv_to_direction(u, v);
+        let pdf = self.pdf(direction);
+        if pdf <= 0. {
+            return None;
+        }
+
+        Some((direction, pdf))
+    }
+
+    pub fn pdf(&self, direction: Tuple) -> f64 {
+        let width = *self.image.get_width();
+        let height = *self.image.get_height();
+        let total = *self.row_cdf.last().unwrap_or(&0.);
+        if width == 0 || height == 0 || total <= 0. {
+            return 0.;
+        }
+
+        let (x, y) = self.pixel_for(direction);
+        let row_cdf = &self.column_cdfs[y];
+        let weight = row_cdf[x + 1] - row_cdf[x];
+        let sin_theta = (1. - direction.normalize().y.powi(2)).max(0.).sqrt();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+
+        // Samples are uniform across the pixel in image space, so convert
+        // that density to solid angle at the sampled direction.
+        (weight / total) * (width * height) as f64 / (2. * PI * PI * sin_theta)
+    }
+
+    fn pixel_for(&self, direction: Tuple) -> (usize, usize) {
+        let width = *self.image.get_width();
+        let height = *self.image.get_height();
+        let (u, v) = Self::direction_to_uv(direction);
+
+        let x = ((u * width as f64) as usize).min(width.saturating_sub(1));
+        let y = ((v * height as f64) as usize).min(height.saturating_sub(1));
+
+        (x, y)
+    }
+}
+
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.red + 0.7152 * color.green + 0.0722 * color.blue
+}
+
+fn cumulative(weights: &[f64]) -> Vec<f64> {
+    let mut cdf = Vec::with_capacity(weights.len() + 1);
+    cdf.push(0.);
+    for weight in weights {
+        cdf.push(cdf.last().unwrap() + weight);
+    }
+
+    cdf
+}
+
+// Returns the chosen bucket and a continuous position in [0, 1] across all
+// buckets.
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let count = cdf.len() - 1;
+    let total = cdf[count];
+    let target = u * total;
+
+    let index = cdf.partition_point(|value| *value <= target).clamp(1, count) - 1;
+    let width = cdf[index + 1] - cdf[index];
+    let offset = if width > 0. { (target - cdf[index]) / width } else { 0.5 };
+
+    (index, (index as f64 + offset.clamp(0., 1.)) / count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::near_eq;
+    use super::super::random::Rng;
+    use super::super::WHITE;
+
+    #[test]
+    fn default_background_is_black() {
+        let background: Background = Default::default();
+
+        let expected = BLACK;
+
+        let actual = background.color_in(Tuple::vector(0., 1., 0.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn gradient_sky_blends_by_height() {
+        let sky = GradientSky::new(Color::new(0., 0., 1.), WHITE, Color::new(0.2, 0.2, 0.2));
+
+        assert_eq!(Color::new(0., 0., 1.), sky.color_in(Tuple::vector(0., 1., 0.)));
+        assert_eq!(WHITE, sky.color_in(Tuple::vector(1., 0., 0.)));
+        assert_eq!(Color::new(0.2, 0.2, 0.2), sky.color_in(Tuple::vector(0., -2., 0.)));
+        assert_eq!(Color::new(0.5, 0.5, 1.), sky.color_in(Tuple::vector(0., 0.5, 0.75_f64.sqrt())));
+    }
+
+    #[test]
+    fn directions_and_image_coordinates_round_trip() {
+        let directions = [Tuple::vector(0., 0., -1.), Tuple::vector(1., 0., 0.),
+            Tuple::vector(0.3, 0.4, 0.5).normalize(), Tuple::vector(-0.6, -0.2, 0.1).normalize()];
+
+        for direction in directions.iter() {
+            let (u, v) = EnvironmentMap::direction_to_uv(*direction);
+
+            assert_eq!(*direction, EnvironmentMap::uv_to_direction(u, v));
+        }
+    }
+
+    #[test]
+    fn image_center_faces_negative_z() {
+        let expected = (0.5, 0.5);
+
+        let actual = EnvironmentMap::direction_to_uv(Tuple::vector(0., 0., -1.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn environment_map_looks_up_pixels_by_direction() {
+        let mut image = Canvas::new(4, 2);
+        image.write_pixel(2, 0, Color::new(1., 0., 0.));
+        image.write_pixel(1, 1, Color::new(0., 1., 0.));
+        let environment = EnvironmentMap::new(image).with_intensity(2.);
+
+        assert_eq!(Color::new(2., 0., 0.), environment.color_in(Tuple::vector(-0.1, 0.5, -1.)));
+        assert_eq!(Color::new(0., 2., 0.), environment.color_in(Tuple::vector(0.1, -0.5, -1.)));
+        assert_eq!(BLACK, environment.color_in(Tuple::vector(0., 0., 1.)));
+    }
+
+    #[test]
+    fn sampling_favours_bright_pixels() {
+        let mut image = Canvas::new(8, 4);
+        image.write_pixel(5, 1, Color::new(10., 10., 10.));
+        let environment = EnvironmentMap::new(image);
+        let mut rng = Rng::new(1);
+
+        for _ in 0..50 {
+            let (direction, pdf) = environment.sample(rng.next_f64(), rng.next_f64()).unwrap();
+
+            assert_eq!(Color::new(10., 10., 10.), environment.color_in(direction));
+            assert!(near_eq(pdf, environment.pdf(direction)));
+        }
+    }
+
+    #[test]
+    fn sampling_pdf_integrates_to_one() {
+        let mut image = Canvas::new(16, 8);
+        for y in 0..8 {
+            for x in 0..16 {
+                image.write_pixel(x, y, Color::new(x as f64 + 1., y as f64, 1.));
+            }
+        }
+        let environment = EnvironmentMap::new(image);
+        let mut rng = Rng::new(2);
+
+        // Uniform sphere sampling estimates the integral of the pdf.
+        let count = 20000;
+        let mut sum = 0.;
+        for _ in 0..count {
+            let z = 1. - 2. * rng.next_f64();
+            let r = (1. - z * z).sqrt();
+            let phi = 2. * PI * rng.next_f64();
+            let direction = Tuple::vector(r * phi.cos(), r * phi.sin(), z);
+
+            sum += environment.pdf(direction) * 4. * PI;
+        }
+
+        assert!((sum / count as f64 - 1.).abs() < 0.05);
+    }
+
+    #[test]
+    fn black_environment_cannot_be_sampled() {
+        let environment = EnvironmentMap::new(Canvas::new(4, 2));
+
+        assert!(environment.sample(0.5, 0.5).is_none());
+    }
+}
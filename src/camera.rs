@@ -1,11 +1,36 @@
+use super::BLACK;
+use super::ambient_occlusion::AmbientOcclusion;
 use super::canvas::Canvas;
-use super::DEFAULT_RECURSION;
+use super::color::Color;
+use super::EPSILON;
+use super::integrator::Integrator;
+use super::intersection::Intersection;
 use super::matrix::Matrix;
 use super::near_eq;
 use super::ORIGIN;
+use super::random::Rng;
 use super::ray::Ray;
+use super::sampling::{AdaptiveSampling, concentric_disk, ReconstructionFilter, regular_polygon, SamplePattern};
+use super::spectrum::{sample_wavelength, spectral_weight};
 use super::tuple::Tuple;
+use super::WHITE;
 use super::world::World;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+pub const DEFAULT_TILE_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel rays covering a view of the given width in world units.
+    Orthographic (f64),
+    /// Equidistant fisheye; the field of view spans the shorter canvas side.
+    Fisheye,
+    /// A full 360 by 180 degree latitude-longitude panorama.
+    Equirectangular,
+}
 
 pub struct Camera {
     pub hsize: usize,
@@ -15,6 +40,30 @@ pub struct Camera {
     pub half_width: f64,
     pub half_height: f64,
     pub pixel_size: f64,
+    pub threads: usize,
+    pub tile_size: usize,
+    pub samples: usize,
+    pub sample_pattern: SamplePattern,
+    pub filter: ReconstructionFilter,
+    pub seed: u64,
+    pub adaptive: Option<AdaptiveSampling>,
+    pub aperture_radius: f64,
+    pub focal_distance: f64,
+    pub aperture_blades: u32,
+    pub projection: Projection,
+    pub integrator: Integrator,
+    /// Traces each sample at a single wavelength, so that materials with
+    /// dispersion split light into its colors. Needs many samples per pixel.
+    pub spectral: bool,
+}
+
+// A rectangular block of pixels traced as a single unit of work.
+#[derive(Debug, Copy, Clone)]
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
 }
 
 impl Camera {
@@ -30,56 +79,447 @@ impl Camera {
 
         let pixel_size = (half_width * 2.) / hsize as f64;
 
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
         Camera { hsize, vsize, field_of_view, transform: Matrix::identity(4),
-            half_width, half_height, pixel_size }
+            half_width, half_height, pixel_size, threads, tile_size: DEFAULT_TILE_SIZE,
+            samples: 1, sample_pattern: SamplePattern::Jittered, filter: ReconstructionFilter::Box,
+            seed: 0, adaptive: None, aperture_radius: 0., focal_distance: 1., aperture_blades: 0,
+            projection: Projection::Perspective, integrator: Integrator::Whitted,
+            spectral: false }
     }
 
     pub fn ray_for_pixel(&self, px: u32, py: u32) -> Ray {
-        let x_offset = (px as f64 + 0.5) * self.pixel_size;
-        let y_offset = (py as f64 + 0.5) * self.pixel_size;
-
-        let world_x = self.half_width - x_offset;
-        let world_y = self.half_height - y_offset;
+        self.ray_for_point(px as f64 + 0.5, py as f64 + 0.5)
+    }
 
-        if self.transform.inverse().is_none() {
+    /// Casts a ray through an arbitrary point on the canvas, where (0, 0) is
+    /// the top left corner of the first pixel.
+    pub fn ray_for_point(&self, x: f64, y: f64) -> Ray {
+        let inverted_transform = self.transform.inverse();
+        if inverted_transform.is_none() {
             return Ray::new(ORIGIN, ORIGIN);
         }
+        let inverted_transform = inverted_transform.unwrap();
+
+        match self.projection {
+            Projection::Perspective => {
+                let world_x = self.half_width - x * self.pixel_size;
+                let world_y = self.half_height - y * self.pixel_size;
+
+                let pixel = inverted_transform.clone() * Tuple::point(world_x, world_y, -1.);
+                let origin = inverted_transform * ORIGIN;
+                let direction = (pixel - origin).normalize();
+
+                Ray::new(origin, direction)
+            },
+            Projection::Orthographic(width) => {
+                let pixel_size = width / self.hsize as f64;
+                let world_x = width / 2. - x * pixel_size;
+                let world_y = (pixel_size * self.vsize as f64) / 2. - y * pixel_size;
+
+                let origin = inverted_transform.clone() * Tuple::point(world_x, world_y, 0.);
+                let direction = (inverted_transform * Tuple::vector(0., 0., -1.)).normalize();
+
+                Ray::new(origin, direction)
+            },
+            Projection::Fisheye => {
+                let radius = self.hsize.min(self.vsize) as f64 / 2.;
+                let dx = (self.hsize as f64 / 2. - x) / radius;
+                let dy = (self.vsize as f64 / 2. - y) / radius;
+                let distance = (dx * dx + dy * dy).sqrt();
+
+                // The angle from the view direction grows linearly with the
+                // distance from the center of the canvas.
+                let theta = distance * self.field_of_view / 2.;
+                let local_direction = if near_eq(distance, 0.) {
+                    Tuple::vector(0., 0., -1.)
+                } else {
+                    Tuple::vector(theta.sin() * dx / distance, theta.sin() * dy / distance, -theta.cos())
+                };
+
+                let origin = inverted_transform.clone() * ORIGIN;
+                let direction = (inverted_transform * local_direction).normalize();
+
+                Ray::new(origin, direction)
+            },
+            Projection::Equirectangular => {
+                let longitude = (x / self.hsize as f64 - 0.5) * 2. * PI;
+                let latitude = (0.5 - y / self.vsize as f64) * PI;
+                let local_direction = Tuple::vector(-longitude.sin() * latitude.cos(), latitude.sin(),
+                    -longitude.cos() * latitude.cos());
+
+                let origin = inverted_transform.clone() * ORIGIN;
+                let direction = (inverted_transform * local_direction).normalize();
+
+                Ray::new(origin, direction)
+            },
+        }
+    }
+
+    /// Casts a ray from a point on the lens through a point on the canvas.
+    /// The lens point is given in the unit disk and scaled by the aperture;
+    /// every ray for the same canvas point meets at the focal distance.
+    pub fn ray_through_lens(&self, x: f64, y: f64, lens_x: f64, lens_y: f64) -> Ray {
+        if near_eq(self.aperture_radius, 0.) || self.projection != Projection::Perspective {
+            return self.ray_for_point(x, y);
+        }
+
+        let world_x = self.half_width - x * self.pixel_size;
+        let world_y = self.half_height - y * self.pixel_size;
 
         let inverted_transform = self.transform.inverse();
-        let (origin, direction) = if inverted_transform.is_none() {
-            (ORIGIN, ORIGIN)
+        if inverted_transform.is_none() {
+            return Ray::new(ORIGIN, ORIGIN);
+        }
+
+        let inverted_transform = inverted_transform.unwrap();
+        let focus = inverted_transform.clone() * Tuple::point(world_x * self.focal_distance,
+            world_y * self.focal_distance, -self.focal_distance);
+        let origin = inverted_transform * Tuple::point(lens_x * self.aperture_radius,
+            lens_y * self.aperture_radius, 0.);
+
+        Ray::new(origin, (focus - origin).normalize())
+    }
+
+    /// Maps a point in the unit square onto the aperture shape, which is a
+    /// disk unless the camera has at least three aperture blades.
+    pub fn sample_aperture(&self, u: f64, v: f64) -> (f64, f64) {
+        if self.aperture_blades >= 3 {
+            regular_polygon(self.aperture_blades, u, v)
         } else {
-            let pixel = inverted_transform.clone().unwrap() * Tuple::point(world_x, world_y, -1.);
-            let origin = inverted_transform.unwrap() * ORIGIN;
-            let direction = (pixel - origin).normalize();
+            concentric_disk(u, v)
+        }
+    }
 
-            (origin, direction)
-        };
-        
-        Ray::new(origin, direction)
+    pub fn color_at_pixel(&self, world: &World, px: u32, py: u32) -> Color {
+        let mut rng = Rng::from_values(&[self.seed, px as u64, py as u64]);
+        let pinhole = near_eq(self.aperture_radius, 0.);
+        if self.samples <= 1 && pinhole {
+            return self.radiance(world, self.ray_for_pixel(px, py), (0, 1), &mut rng);
+        }
+
+        let radius = if self.samples <= 1 { 0. } else { self.filter.radius() };
+
+        let offsets = self.sample_pattern.offsets(self.samples, &mut rng);
+        // Shuffle the lens samples so they are not correlated with the pixel
+        // samples drawn from the same pattern.
+        let mut lens_offsets = self.sample_pattern.offsets(self.samples, &mut rng);
+        rng.shuffle(&mut lens_offsets);
+
+        let count = offsets.len();
+        let mut color = BLACK;
+        let mut unweighted = BLACK;
+        let mut total_weight = 0.;
+        let mut lowest = Color::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut highest = Color::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for (i, ((u, v), (lens_u, lens_v))) in offsets.into_iter().zip(lens_offsets).enumerate() {
+            // Spread the samples over the whole footprint of the filter.
+            let dx = (u - 0.5) * 2. * radius;
+            let dy = (v - 0.5) * 2. * radius;
+            let weight = if self.samples <= 1 { 1. } else { self.filter.weight(dx, dy) };
+
+            let (lens_x, lens_y) = self.sample_aperture(lens_u, lens_v);
+            let ray = self.ray_through_lens(px as f64 + 0.5 + dx, py as f64 + 0.5 + dy, lens_x, lens_y);
+            let radiance = self.radiance(world, ray, (i, count), &mut rng);
+            color += radiance * weight;
+            unweighted += radiance;
+            total_weight += weight;
+            lowest = Color::new(lowest.red.min(radiance.red), lowest.green.min(radiance.green),
+                lowest.blue.min(radiance.blue));
+            highest = Color::new(highest.red.max(radiance.red), highest.green.max(radiance.green),
+                highest.blue.max(radiance.blue));
+        }
+
+        // The negative lobes of some filters can cancel out the rest of the
+        // weight, leaving too little to normalize by.
+        if total_weight <= EPSILON {
+            return unweighted * (1. / count as f64);
+        }
+
+        // They can also make the pixel ring past its samples, even below
+        // zero, so keep it within their range.
+        let color = color * (1. / total_weight);
+
+        Color::new(color.red.clamp(lowest.red, highest.red).max(0.),
+            color.green.clamp(lowest.green, highest.green).max(0.),
+            color.blue.clamp(lowest.blue, highest.blue).max(0.))
+    }
+
+    // The light arriving along the ray. Spectral samples are spread over the
+    // visible spectrum, each taking the given stratum of it.
+    fn radiance(&self, world: &World, ray: Ray, stratum: (usize, usize), rng: &mut Rng) -> Color {
+        if !self.spectral {
+            return self.integrator.radiance(world, ray, rng);
+        }
+
+        let (index, count) = stratum;
+        let wavelength = sample_wavelength((index as f64 + rng.next_f64()) / count as f64);
+
+        self.integrator.radiance(world, ray.with_wavelength(Some(wavelength)), rng) * spectral_weight(wavelength)
     }
 
     pub fn render(&self, world: World) -> Canvas {
-        let mut image = Canvas::new(self.hsize, self.vsize);
+        if self.adaptive.is_some() {
+            return self.render_with_heatmap(world).0;
+        }
+
+        let colors = self.render_tiles(|x, y| self.color_at_pixel(&world, x, y));
+
+        self.canvas_from(colors)
+    }
+
+    /// Renders the world with adaptive anti-aliasing, returning the image
+    /// along with a heatmap of how many rays were traced for each pixel.
+    /// Every point sampled on the canvas is traced with `samples` rays
+    /// spread over the lens. Falls back to sampling only the pixel centers if
+    /// no adaptive settings are given.
+    pub fn render_with_heatmap(&self, world: World) -> (Canvas, Canvas) {
+        let refined = self.adaptive_samples(&world);
+
+        let fewest_samples = refined.iter().map(|(_, samples)| *samples).min().unwrap_or(1);
+        let most_samples = refined.iter().map(|(_, samples)| *samples).max().unwrap_or(1);
+        let heatmap = refined.iter().map(|(_, samples)| Self::heatmap_color(*samples, fewest_samples, most_samples))
+            .collect::<Vec<Color>>();
+        let colors = refined.into_iter().map(|(color, _)| color).collect::<Vec<Color>>();
+
+        (self.canvas_from(colors), self.canvas_from(heatmap))
+    }
+
+    // The color of every pixel and the number of rays traced for it. Corners
+    // shared by neighbouring pixels are traced once, for the first refined
+    // pixel below and to the right of them.
+    fn adaptive_samples(&self, world: &World) -> Vec<(Color, usize)> {
+        let settings = self.adaptive.unwrap_or(AdaptiveSampling::new(f64::INFINITY, 0));
+        let (hsize, vsize) = (self.hsize as i64, self.vsize as i64);
+
+        let centers = self.render_tiles(|x, y| self.color_at_point(world, x as f64 + 0.5, y as f64 + 0.5));
+        let refine = (0..self.hsize * self.vsize).map(|index| {
+            let (x, y) = ((index % self.hsize) as i64, (index / self.hsize) as i64);
 
-        for y in 0..self.vsize - 1 {
-            for x in 0..self.hsize - 1 {
-                let ray = self.ray_for_pixel(x as u32, y as u32);
-                let color = world.color_at(ray, DEFAULT_RECURSION);
-                image.write_pixel(x as u32, y as u32, color);
+            [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+                let (nx, ny) = (x + dx, y + dy);
+                let inside = nx >= 0 && ny >= 0 && nx < hsize && ny < vsize;
+
+                inside && Self::contrast(centers[index].0, centers[(ny * hsize + nx) as usize].0) > settings.threshold
+            })
+        }).collect::<Vec<bool>>();
+        let tracer_of_corner = |cx: i64, cy: i64| {
+            [(0, 0), (-1, 0), (0, -1), (-1, -1)].iter().map(|(dx, dy)| (cx + dx, cy + dy))
+                .find(|&(x, y)| x >= 0 && y >= 0 && x < hsize && y < vsize && refine[(y * hsize + x) as usize])
+        };
+
+        let traced_corners = self.render_tiles(|x, y| {
+            let (x, y) = (x as i64, y as i64);
+
+            [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].iter()
+                .filter(|&&(cx, cy)| tracer_of_corner(cx, cy) == Some((x, y)))
+                .map(|&(cx, cy)| ((cx, cy), self.color_at_point(world, cx as f64, cy as f64)))
+                .collect::<Vec<((i64, i64), (Color, usize))>>()
+        });
+        let mut corners = vec![BLACK; (self.hsize + 1) * (self.vsize + 1)];
+        for ((cx, cy), (color, _)) in traced_corners.iter().flatten() {
+            corners[(cy * (hsize + 1) + cx) as usize] = *color;
+        }
+
+        self.render_tiles(|x, y| {
+            let index = y as usize * self.hsize + x as usize;
+            let (center, center_samples) = centers[index];
+            if !refine[index] {
+                return (center, center_samples);
+            }
+
+            let corner_at = |cx: u32, cy: u32| corners[cy as usize * (self.hsize + 1) + cx as usize];
+            let pixel_corners = [corner_at(x, y), corner_at(x + 1, y), corner_at(x, y + 1), corner_at(x + 1, y + 1)];
+            let corner_samples = traced_corners[index].iter().map(|(_, (_, samples))| samples).sum::<usize>();
+            let (color, samples) = self.subdivide(world, (x as f64, y as f64, 1.), pixel_corners, center, 0,
+                &settings);
+
+            (color, center_samples + corner_samples + samples)
+        })
+    }
+
+    /// Renders the ambient occlusion of the world on its own, from white for
+    /// open surfaces to black for fully enclosed ones, for compositing.
+    /// Rays that miss every object are white.
+    pub fn render_ambient_occlusion(&self, world: World, ambient_occlusion: &AmbientOcclusion) -> Canvas {
+        let colors = self.render_tiles(|x, y| {
+            let ray = self.ray_for_pixel(x, y);
+            let intersections = world.intersect_world(ray);
+
+            match Intersection::hit(intersections.clone()) {
+                Some(hit) => {
+                    let computations = hit.prepare_computations(ray, intersections);
+
+                    WHITE * ambient_occlusion.accessibility(&world, &computations)
+                },
+                None => WHITE,
             }
+        });
+
+        self.canvas_from(colors)
+    }
+
+    // The color seen through the lens at a point on the canvas, averaged
+    // over `samples` rays, along with the number of rays traced.
+    fn color_at_point(&self, world: &World, x: f64, y: f64) -> (Color, usize) {
+        let mut rng = Rng::from_values(&[self.seed, x.to_bits(), y.to_bits()]);
+        let lens_offsets = self.sample_pattern.offsets(self.samples.max(1), &mut rng);
+        let count = lens_offsets.len();
+
+        let mut color = BLACK;
+        for (i, (lens_u, lens_v)) in lens_offsets.into_iter().enumerate() {
+            let (lens_x, lens_y) = self.sample_aperture(lens_u, lens_v);
+            color += self.radiance(world, self.ray_through_lens(x, y, lens_x, lens_y), (i, count), &mut rng);
+        }
+
+        (color * (1. / count as f64), count)
+    }
+
+    // Refines a square region of the canvas, given as its top left corner
+    // and size, whose corners and center are already traced. While they
+    // disagree, the region is split into quadrants that share those samples.
+    // Returns the averaged color and the number of rays traced for it.
+    fn subdivide(&self, world: &World, region: (f64, f64, f64), corners: [Color; 4], center: Color, depth: u32,
+        settings: &AdaptiveSampling) -> (Color, usize) {
+        let (x, y, size) = region;
+        let known = [corners[0], corners[1], corners[2], corners[3], center];
+        let uniform = known.iter().all(|a| known.iter().all(|b| Self::contrast(*a, *b) <= settings.threshold));
+        if uniform || depth >= settings.max_depth {
+            let sum = known.iter().fold(BLACK, |sum, c| sum + *c);
+            return (sum * (1. / known.len() as f64), 0);
+        }
+
+        let half = size / 2.;
+        let quarter = half / 2.;
+        let [top_left, top_right, bottom_left, bottom_right] = corners;
+        let edges = [(x + half, y), (x, y + half), (x + size, y + half), (x + half, y + size)]
+            .map(|(ex, ey)| self.color_at_point(world, ex, ey));
+        let [top, left, right, bottom] = edges.map(|(color, _)| color);
+        let quadrants = [
+            ((x, y), [top_left, top, left, center]),
+            ((x + half, y), [top, top_right, center, right]),
+            ((x, y + half), [left, center, bottom_left, bottom]),
+            ((x + half, y + half), [center, right, bottom, bottom_right]),
+        ];
+
+        let samples = edges.iter().map(|(_, samples)| samples).sum::<usize>();
+        let (sum, samples) = quadrants.iter().fold((BLACK, samples), |(sum, samples), ((qx, qy), corners)| {
+            let (center, center_samples) = self.color_at_point(world, qx + quarter, qy + quarter);
+            let (color, count) = self.subdivide(world, (*qx, *qy, half), *corners, center, depth + 1, settings);
+            (sum + color, samples + center_samples + count)
+        });
+
+        (sum * 0.25, samples)
+    }
+
+    fn contrast(a: Color, b: Color) -> f64 {
+        (a.red - b.red).abs().max((a.green - b.green).abs()).max((a.blue - b.blue).abs())
+    }
+
+    // Blue for the fewest samples, through green, to red for the most.
+    fn heatmap_color(samples: usize, fewest_samples: usize, most_samples: usize) -> Color {
+        let t = if most_samples <= fewest_samples {
+            0.
+        } else {
+            (samples - fewest_samples) as f64 / (most_samples - fewest_samples) as f64
+        };
+
+        if t < 0.5 {
+            Color::new(0., t * 2., 1. - t * 2.)
+        } else {
+            Color::new((t - 0.5) * 2., 1. - (t - 0.5) * 2., 0.)
+        }
+    }
+
+    fn canvas_from(&self, colors: Vec<Color>) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        for (index, color) in colors.into_iter().enumerate() {
+            image.write_pixel((index % self.hsize) as u32, (index / self.hsize) as u32, color);
         }
 
         image
     }
+
+    fn tiles(&self) -> Vec<Tile> {
+        let tile_size = self.tile_size.max(1);
+        let mut tiles = vec![];
+
+        for y in (0..self.vsize).step_by(tile_size) {
+            for x in (0..self.hsize).step_by(tile_size) {
+                tiles.push(Tile { x, y, width: tile_size.min(self.hsize - x),
+                    height: tile_size.min(self.vsize - y) });
+            }
+        }
+
+        tiles
+    }
+
+    /// Evaluates `trace` for every pixel, handing out tiles to a pool of
+    /// `threads` workers. The results are returned in row-major order, so
+    /// the output does not depend on how the tiles were scheduled.
+    pub fn render_tiles<T, F>(&self, trace: F) -> Vec<T>
+        where T: Send, F: Fn(u32, u32) -> T + Sync {
+        let tiles = self.tiles();
+        let next_tile = AtomicUsize::new(0);
+
+        let worker = || {
+            let mut traced = vec![];
+            loop {
+                let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                if index >= tiles.len() {
+                    break;
+                }
+
+                let tile = tiles[index];
+                let mut values = Vec::with_capacity(tile.width * tile.height);
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        values.push(trace(x as u32, y as u32));
+                    }
+                }
+                traced.push((tile, values));
+            }
+
+            traced
+        };
+
+        let threads = self.threads.clamp(1, tiles.len().max(1));
+        let traced = if threads == 1 {
+            worker()
+        } else {
+            thread::scope(|scope| {
+                let handles = (0..threads).map(|_| scope.spawn(worker)).collect::<Vec<_>>();
+                handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+            })
+        };
+
+        let mut pixels: Vec<Option<T>> = (0..self.hsize * self.vsize).map(|_| None).collect();
+        for (tile, values) in traced {
+            for (offset, value) in values.into_iter().enumerate() {
+                let x = tile.x + offset % tile.width;
+                let y = tile.y + offset / tile.width;
+                pixels[y * self.hsize + x] = Some(value);
+            }
+        }
+
+        pixels.into_iter().map(|pixel| pixel.unwrap()).collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::background::Background;
     use super::super::color::Color;
+    use super::super::DEFAULT_RECURSION;
+    use super::super::EPSILON;
+    use super::super::integrator::PathTracing;
+    use super::super::material::Material;
     use super::super::matrix::Matrix;
+    use super::super::plane::Plane;
     use super::super::ray::Ray;
+    use super::super::shape::{CommonShape, Shape};
     use super::super::transformation::*;
     use super::super::tuple::Tuple;
     use super::super::world::World;
@@ -176,4 +616,461 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn camera_uses_whitted_integrator_by_default() {
+        let camera = Camera::new(11, 11, PI / 2.);
+
+        assert_eq!(Integrator::Whitted, camera.integrator);
+    }
+
+    #[test]
+    fn rendering_with_path_tracer_is_reproducible() {
+        let world = World { background: Background::Color(Color::new(0.5, 0.5, 0.5)), ..Default::default() };
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.integrator = Integrator::PathTracer(PathTracing::default());
+        camera.samples = 4;
+        camera.seed = 9;
+
+        let expected = camera.render(Default::default());
+
+        let actual = camera.render(Default::default());
+
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_eq!(expected.pixel_at(x, y), actual.pixel_at(x, y));
+            }
+        }
+        assert!(camera.render(world).pixel_at(0, 0).red > 0.);
+    }
+
+    #[test]
+    fn path_traced_pixel_uses_camera_integrator() {
+        let world: World = Default::default();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.integrator = Integrator::PathTracer(PathTracing::new(1, 1));
+        let ambient = Color::new(0.8, 1., 0.6) * 0.1;
+
+        let expected = world.color_at(camera.ray_for_pixel(5, 5), DEFAULT_RECURSION) - ambient;
+
+        let actual = camera.color_at_pixel(&world, 5, 5);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rendering_ambient_occlusion_pass() {
+        let mut world = World::new();
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_transform(translate(0., 1., 0.));
+        world.objects.push(ceiling);
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0.5, 0.), Tuple::point(0., 0.5, -1.),
+            Tuple::vector(0., 1., 0.));
+        let ambient_occlusion = AmbientOcclusion::new(16, 100.);
+
+        let image = camera.render_ambient_occlusion(world, &ambient_occlusion);
+
+        assert_eq!(BLACK, image.pixel_at(5, 10));
+        assert_eq!(BLACK, image.pixel_at(5, 0));
+        assert_eq!(WHITE, image.pixel_at(5, 5));
+    }
+
+    #[test]
+    fn tiles_cover_canvas_without_overlap() {
+        let mut camera = Camera::new(10, 7, PI / 2.);
+        camera.tile_size = 4;
+
+        let expected_count = 6;
+        let expected_area = 70;
+
+        let actual = camera.tiles();
+
+        assert_eq!(expected_count, actual.len());
+        assert_eq!(expected_area, actual.iter().map(|t| t.width * t.height).sum::<usize>());
+    }
+
+    #[test]
+    fn world_can_be_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<World>();
+        assert_send_sync::<Camera>();
+    }
+
+    #[test]
+    fn rendering_in_parallel_matches_single_thread() {
+        let mut camera = Camera::new(21, 15, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.tile_size = 4;
+
+        camera.threads = 1;
+        let expected = camera.render(Default::default());
+
+        camera.threads = 4;
+        let actual = camera.render(Default::default());
+
+        for y in 0..15 {
+            for x in 0..21 {
+                let expected_pixel = expected.pixel_at(x, y);
+                let actual_pixel = actual.pixel_at(x, y);
+
+                assert_eq!(expected_pixel.red.to_bits(), actual_pixel.red.to_bits());
+                assert_eq!(expected_pixel.green.to_bits(), actual_pixel.green.to_bits());
+                assert_eq!(expected_pixel.blue.to_bits(), actual_pixel.blue.to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn single_sample_uses_pixel_center() {
+        let world: World = Default::default();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+
+        let expected = world.color_at(camera.ray_for_pixel(5, 5), DEFAULT_RECURSION);
+
+        let actual = camera.color_at_pixel(&world, 5, 5);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn supersampling_is_reproducible_with_seed() {
+        let world: World = Default::default();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.samples = 4;
+        camera.seed = 17;
+
+        let expected = camera.color_at_pixel(&world, 3, 4);
+
+        let actual = camera.color_at_pixel(&world, 3, 4);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn supersampling_blends_colors_along_edges() {
+        let mut world: World = Default::default();
+        world.objects.remove(1);
+        world.objects[0].set_material(Material::new().with_ambient(1.).with_diffuse(0.).with_specular(0.));
+        let mut camera = Camera::new(101, 101, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+
+        // Find a pixel on the silhouette of the sphere whose center misses.
+        let (x, y) = (0..101).map(|x| (x, 50)).find(|&(x, y)| {
+            camera.color_at_pixel(&world, x, y) == BLACK &&
+                camera.color_at_pixel(&world, x + 1, y) != BLACK
+        }).unwrap();
+        let inside = camera.color_at_pixel(&world, x + 1, y);
+
+        camera.samples = 16;
+        camera.sample_pattern = SamplePattern::Regular;
+        camera.filter = ReconstructionFilter::Tent;
+        let actual = camera.color_at_pixel(&world, x, y);
+
+        assert!(actual.red > 0.);
+        assert!(actual.red < inside.red);
+    }
+
+    #[test]
+    fn mitchell_filter_keeps_pixels_within_range_of_samples() {
+        let mut world: World = Default::default();
+        world.objects.remove(1);
+        world.objects[0].set_material(Material::new().with_ambient(1.).with_diffuse(0.).with_specular(0.));
+        let mut camera = Camera::new(101, 101, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        // Every sample either misses the sphere or sees its flat color.
+        let inside = camera.color_at_pixel(&world, 50, 50);
+
+        camera.samples = 2;
+        camera.filter = ReconstructionFilter::Mitchell;
+
+        for seed in 0..4 {
+            camera.seed = seed;
+            for x in 0..101 {
+                let actual = camera.color_at_pixel(&world, x, 50);
+
+                assert!(actual.red >= 0. && actual.red <= inside.red);
+                assert!(actual.green >= 0. && actual.green <= inside.green);
+                assert!(actual.blue >= 0. && actual.blue <= inside.blue);
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_refines_only_high_contrast_pixels() {
+        let mut world: World = Default::default();
+        world.objects.remove(1);
+        world.objects[0].set_material(Material::new().with_ambient(1.).with_diffuse(0.).with_specular(0.));
+        let mut camera = Camera::new(21, 21, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.adaptive = Some(AdaptiveSampling::new(0.1, 2));
+
+        let (_image, heatmap) = camera.render_with_heatmap(world);
+
+        let expected_flat = Color::new(0., 0., 1.);
+
+        let actual_background = heatmap.pixel_at(0, 0);
+        let actual_interior = heatmap.pixel_at(10, 10);
+        let actual_edge = (0..21).map(|x| heatmap.pixel_at(x, 10)).find(|c| *c != expected_flat);
+
+        assert_eq!(expected_flat, actual_background);
+        assert_eq!(expected_flat, actual_interior);
+        assert!(actual_edge.is_some());
+    }
+
+    #[test]
+    fn adaptive_sampling_leaves_flat_regions_untouched() {
+        let world: World = Default::default();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+
+        let expected = world.color_at(camera.ray_for_pixel(0, 0), DEFAULT_RECURSION);
+
+        camera.adaptive = Some(AdaptiveSampling::new(0.1, 3));
+        let actual = camera.render(world).pixel_at(0, 0);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn subdividing_uniform_region_traces_nothing_new() {
+        let world = World::new();
+        let camera = Camera::new(11, 11, PI / 2.);
+        let settings = AdaptiveSampling::new(0.1, 4);
+        let gray = Color::new(0.5, 0.5, 0.5);
+
+        let expected = (gray, 0);
+
+        let actual = camera.subdivide(&world, (2., 2., 1.), [gray; 4], gray, 0, &settings);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn subdividing_region_reuses_its_corners_and_center() {
+        let world = World::new();
+        let camera = Camera::new(11, 11, PI / 2.);
+        let settings = AdaptiveSampling::new(0.1, 1);
+
+        // Only the middle of each edge and the centers of the quadrants are
+        // new; the quadrants blend the white center into the black corners.
+        let expected = (WHITE * 0.2, 8);
+
+        let actual = camera.subdivide(&world, (2., 2., 1.), [BLACK; 4], WHITE, 0, &settings);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn adaptive_sampling_traces_shared_corners_once() {
+        let mut camera = Camera::new(21, 21, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.adaptive = Some(AdaptiveSampling::new(0.1, 0));
+        camera.tile_size = 4;
+
+        let samples = camera.adaptive_samples(&Default::default());
+
+        // Refined pixels trace at least their top left corner on top of their
+        // center, and nothing else without further subdivision.
+        let mut corners = vec![];
+        for (index, _) in samples.iter().enumerate().filter(|(_, (_, samples))| *samples > 1) {
+            let (x, y) = (index % 21, index / 21);
+            for corner in [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].iter() {
+                if !corners.contains(corner) {
+                    corners.push(*corner);
+                }
+            }
+        }
+
+        let expected = 21 * 21 + corners.len();
+
+        let actual = samples.iter().map(|(_, samples)| samples).sum::<usize>();
+
+        assert!(!corners.is_empty());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn adaptive_sampling_traces_every_sample_through_lens() {
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.adaptive = Some(AdaptiveSampling::new(0.1, 2));
+        let (sharp, heatmap) = camera.render_with_heatmap(Default::default());
+        let edge = (0..11).find(|x| heatmap.pixel_at(*x, 5) != Color::new(0., 0., 1.)).unwrap();
+
+        camera.samples = 4;
+        camera.aperture_radius = 0.5;
+        let blurred = camera.render(Default::default());
+
+        assert_ne!(sharp.pixel_at(edge, 5), blurred.pixel_at(edge, 5));
+    }
+
+    #[test]
+    fn lens_rays_converge_at_focal_distance() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.transform = rotate(PI / 4., Axis::Y) * translate(0., -2., 5.);
+        camera.aperture_radius = 0.5;
+        camera.focal_distance = 4.;
+
+        let pinhole = camera.ray_for_point(30.5, 20.5);
+        let camera_direction = camera.transform.clone() * pinhole.direction;
+        let focus = pinhole.position(-camera.focal_distance / camera_direction.z);
+
+        for (lens_x, lens_y) in [(0.3, -0.2), (-0.9, 0.1), (0., 1.)].iter() {
+            let actual = camera.ray_through_lens(30.5, 20.5, *lens_x, *lens_y);
+
+            assert_ne!(pinhole.origin, actual.origin);
+            assert_eq!((focus - actual.origin).normalize(), actual.direction);
+        }
+    }
+
+    #[test]
+    fn lens_center_matches_pinhole_ray() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.aperture_radius = 0.25;
+        camera.focal_distance = 3.;
+
+        let expected = camera.ray_for_pixel(0, 0);
+
+        let actual = camera.ray_through_lens(0.5, 0.5, 0., 0.);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn polygonal_aperture_samples_lie_within_radius() {
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.aperture_blades = 5;
+        let mut rng = Rng::new(2);
+
+        for _ in 0..100 {
+            let (x, y) = camera.sample_aperture(rng.next_f64(), rng.next_f64());
+
+            assert!(x * x + y * y <= 1. + EPSILON);
+        }
+    }
+
+    #[test]
+    fn depth_of_field_blurs_out_of_focus_objects() {
+        let mut world: World = Default::default();
+        world.objects.remove(1);
+        world.objects[0].set_material(Material::new().with_ambient(1.).with_diffuse(0.).with_specular(0.));
+        let mut camera = Camera::new(101, 101, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+
+        // Find the last pixel left of the silhouette that misses the sphere.
+        let x = (0..101).find(|x| camera.color_at_pixel(&world, x + 1, 50) != BLACK).unwrap();
+
+        camera.aperture_radius = 0.5;
+        camera.focal_distance = 1.;
+        camera.samples = 16;
+        let actual = camera.color_at_pixel(&world, x, 50);
+
+        assert!(actual.red > 0.);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let mut camera = Camera::new(200, 100, PI / 2.);
+        camera.projection = Projection::Orthographic(4.);
+
+        let expected_center = Ray::new(ORIGIN, Tuple::vector(0., 0., -1.));
+        let expected_corner_origin = Tuple::point(2., 1., 0.);
+
+        let actual_center = camera.ray_for_point(100., 50.);
+        let actual_corner = camera.ray_for_point(0., 0.);
+
+        assert_eq!(expected_center, actual_center);
+        assert_eq!(expected_corner_origin, actual_corner.origin);
+        assert_eq!(actual_center.direction, actual_corner.direction);
+    }
+
+    #[test]
+    fn orthographic_projection_follows_camera_transform() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.transform = rotate(PI / 4., Axis::Y) * translate(0., -2., 5.);
+        camera.projection = Projection::Orthographic(2.);
+
+        let expected = Ray::new(Tuple::point(0., 2., -5.), Tuple::vector(2_f64.sqrt() / 2., 0., -2_f64.sqrt() / 2.));
+
+        let actual = camera.ray_for_pixel(100, 50);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn fisheye_angle_grows_linearly_from_center() {
+        let mut camera = Camera::new(100, 100, PI);
+        camera.projection = Projection::Fisheye;
+
+        let expected_center = Tuple::vector(0., 0., -1.);
+        let expected_edge = Tuple::vector(1., 0., 0.);
+        let expected_halfway = Tuple::vector(0., 2_f64.sqrt() / 2., -2_f64.sqrt() / 2.);
+
+        let actual_center = camera.ray_for_point(50., 50.).direction;
+        let actual_edge = camera.ray_for_point(0., 50.).direction;
+        let actual_halfway = camera.ray_for_point(50., 25.).direction;
+
+        assert_eq!(expected_center, actual_center);
+        assert_eq!(expected_edge, actual_edge);
+        assert_eq!(expected_halfway, actual_halfway);
+    }
+
+    #[test]
+    fn equirectangular_projection_covers_full_sphere() {
+        let mut camera = Camera::new(360, 180, PI / 2.);
+        camera.projection = Projection::Equirectangular;
+
+        let expected_front = Tuple::vector(0., 0., -1.);
+        let expected_back = Tuple::vector(0., 0., 1.);
+        let expected_up = Tuple::vector(0., 1., 0.);
+        let expected_side = Tuple::vector(1., 0., 0.);
+
+        let actual_front = camera.ray_for_point(180., 90.).direction;
+        let actual_back = camera.ray_for_point(0., 90.).direction;
+        let actual_up = camera.ray_for_point(180., 0.).direction;
+        let actual_side = camera.ray_for_point(90., 90.).direction;
+
+        assert_eq!(expected_front, actual_front);
+        assert_eq!(expected_back, actual_back);
+        assert_eq!(expected_up, actual_up);
+        assert_eq!(expected_side, actual_side);
+    }
+
+    #[test]
+    fn rendering_world_with_equirectangular_camera() {
+        let world: World = Default::default();
+        let mut camera = Camera::new(40, 20, PI / 2.);
+        camera.transform = view_transform(Tuple::point(0., 0., -5.), ORIGIN, Tuple::vector(0., 1., 0.));
+        camera.projection = Projection::Equirectangular;
+
+        let expected_hit = world.color_at(camera.ray_for_pixel(20, 10), DEFAULT_RECURSION);
+
+        let image = camera.render(world);
+
+        assert_eq!(expected_hit, image.pixel_at(20, 10));
+        assert_eq!(BLACK, image.pixel_at(0, 10));
+    }
+
+    #[test]
+    fn spectral_samples_add_up_to_white() {
+        let world = World { background: Background::Color(WHITE), ..World::new() };
+        let mut camera = Camera::new(1, 1, PI / 2.);
+        camera.samples = 64;
+        camera.spectral = true;
+
+        let expected = WHITE;
+
+        let actual = camera.color_at_pixel(&world, 0, 0);
+
+        assert!((expected.red - actual.red).abs() < 0.01);
+        assert!((expected.green - actual.green).abs() < 0.01);
+        assert!((expected.blue - actual.blue).abs() < 0.01);
+    }
+}
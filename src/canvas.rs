@@ -1,6 +1,43 @@
 use super::BLACK;
-use super::color::Color;
+use super::color::{Color, TransferFunction};
+use super::hdr::{read_hdr, read_pfm, write_hdr, write_pfm};
+use super::png::{PngBitDepth, read_png, write_png};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+/// The most pixels an image read from a file may have, so that a corrupt
+/// header cannot make the reader allocate without bound.
+pub const MAX_IMAGE_PIXELS: usize = 1 << 26;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PpmFormat {
+    Ascii,
+    Binary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageError {
+    InvalidHeader (String),
+    InvalidData (String),
+    UnexpectedEnd,
+    Unsupported (String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::InvalidHeader(message) => write!(f, "Invalid image header: {}", message),
+            ImageError::InvalidData(message) => write!(f, "Invalid image data: {}", message),
+            ImageError::UnexpectedEnd => write!(f, "Image data ended unexpectedly."),
+            ImageError::Unsupported(message) => write!(f, "Unsupported image: {}", message),
+        }
+    }
+}
+
+impl Error for ImageError {}
 
+#[derive(Debug, Clone)]
 pub struct Canvas {
     width: usize,
     height: usize,
@@ -14,6 +51,117 @@ impl Canvas {
         Canvas { width, height, pixels }
     }
 
+    /// The number of pixels in an image of the given size read from a file,
+    /// or an error if it is too large to hold.
+    pub fn checked_pixel_count(width: usize, height: usize) -> Result<usize, ImageError> {
+        match width.checked_mul(height) {
+            Some(count) if count <= MAX_IMAGE_PIXELS => Ok(count),
+            _ => Err(ImageError::InvalidHeader(format!("{} by {} image is too large", width, height))),
+        }
+    }
+
+    /// Reads an ASCII (P3) or binary (P6) PPM image. Stored values are
+    /// decoded with `transfer`; images written by this crate are linear.
+    pub fn from_ppm(data: &[u8], transfer: TransferFunction) -> Result<Self, ImageError> {
+        let mut position = 0;
+        let magic_number = Self::next_ppm_token(data, &mut position)?;
+        if magic_number != "P3" && magic_number != "P6" {
+            return Err(ImageError::InvalidHeader(format!("unknown magic number {}", magic_number)));
+        }
+
+        let mut header = vec![];
+        for name in ["width", "height", "maximum color value"].iter() {
+            let token = Self::next_ppm_token(data, &mut position)?;
+            let value = token.parse::<usize>().map_err(|_| {
+                ImageError::InvalidHeader(format!("{} is not a valid {}", token, name))
+            })?;
+            header.push(value);
+        }
+
+        let (width, height, max_color) = (header[0], header[1], header[2]);
+        if max_color == 0 || max_color > 65535 {
+            return Err(ImageError::InvalidHeader(format!("maximum color value {} is out of range", max_color)));
+        }
+
+        let sample_count = Self::checked_pixel_count(width, height)? * 3;
+        let mut samples = Vec::with_capacity(sample_count.min(data.len()));
+        if magic_number == "P3" {
+            for _ in 0..sample_count {
+                let token = Self::next_ppm_token(data, &mut position)?;
+                let value = token.parse::<usize>().map_err(|_| {
+                    ImageError::InvalidData(format!("{} is not a color value", token))
+                })?;
+                samples.push(value);
+            }
+        } else {
+            // A single whitespace character separates the header from the
+            // binary data.
+            position += 1;
+            let bytes_per_sample = if max_color > 255 { 2 } else { 1 };
+            let end = position + sample_count * bytes_per_sample;
+            if data.len() < end {
+                return Err(ImageError::UnexpectedEnd);
+            }
+
+            samples.extend(data[position..end].chunks(bytes_per_sample).map(|bytes| {
+                bytes.iter().fold(0, |value, byte| (value << 8) | *byte as usize)
+            }));
+        }
+
+        if let Some(value) = samples.iter().find(|s| **s > max_color) {
+            return Err(ImageError::InvalidData(format!("{} exceeds the maximum color value", value)));
+        }
+
+        let mut canvas = Canvas::new(width, height);
+        for (pixel, channels) in canvas.pixels.iter_mut().zip(samples.chunks(3)) {
+            let decode = |value: usize| transfer.decode(value as f64 / max_color as f64);
+            *pixel = Color::new(decode(channels[0]), decode(channels[1]), decode(channels[2]));
+        }
+
+        Ok(canvas)
+    }
+
+    // Returns the next whitespace delimited token, skipping comments.
+    fn next_ppm_token(data: &[u8], position: &mut usize) -> Result<String, ImageError> {
+        loop {
+            while *position < data.len() && data[*position].is_ascii_whitespace() {
+                *position += 1;
+            }
+
+            if *position < data.len() && data[*position] == b'#' {
+                while *position < data.len() && data[*position] != b'\n' {
+                    *position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+
+        let start = *position;
+        while *position < data.len() && !data[*position].is_ascii_whitespace() && data[*position] != b'#' {
+            *position += 1;
+        }
+
+        if start == *position {
+            return Err(ImageError::UnexpectedEnd);
+        }
+
+        Ok(String::from_utf8_lossy(&data[start..*position]).to_string())
+    }
+
+    /// Reads a PNG image, converting the stored values to linear colors.
+    pub fn from_png(data: &[u8]) -> Result<Self, ImageError> {
+        read_png(data)
+    }
+
+    pub fn from_hdr(data: &[u8]) -> Result<Self, ImageError> {
+        read_hdr(data)
+    }
+
+    pub fn from_pfm(data: &[u8]) -> Result<Self, ImageError> {
+        read_pfm(data)
+    }
+
     pub fn get_width(&self) -> &usize {
         &self.width
     }
@@ -26,67 +174,107 @@ impl Canvas {
         self.pixels[y as usize * self.width as usize + x as usize] = color;
     }
 
-    pub fn pixel_at(self, x: u32, y: u32) -> Color {
+    pub fn pixel_at(&self, x: u32, y: u32) -> Color {
         self.pixels[y as usize * self.width as usize + x as usize]
     }
 
-    pub fn canvas_to_ppm(self) -> String {
-        const MAX_COLOR: i32 = 255;
-        let mut ppm_data = String::new();
+    pub fn canvas_to_ppm(&self) -> String {
+        let mut ppm_data = vec![];
+        self.write_ppm(&mut ppm_data, PpmFormat::Ascii, 255)
+            .expect("Writing to memory cannot fail.");
+
+        String::from_utf8(ppm_data).unwrap()
+    }
+
+    /// Streams the canvas to `writer` one row at a time. Binary files use two
+    /// bytes per channel when the maximum color value is above 255.
+    pub fn write_ppm<W: Write>(&self, writer: &mut W, format: PpmFormat,
+        max_color: u16) -> io::Result<()> {
+        let max_color = max_color.max(1) as i32;
 
         // Write the header data.
-        ppm_data.push_str("P3\n");
-        ppm_data.push_str(format!("{} {}\n", self.width, self.height).as_str());
-        ppm_data.push_str(format!("{}\n", MAX_COLOR).as_str());
+        let magic_number = match format {
+            PpmFormat::Ascii => "P3",
+            PpmFormat::Binary => "P6",
+        };
+        write!(writer, "{}\n{} {}\n{}\n", magic_number, self.width, self.height, max_color)?;
 
         // Write the pixel data.
+        for row in self.pixels.chunks(self.width.max(1)) {
+            match format {
+                PpmFormat::Ascii => writer.write_all(Self::ascii_row(row, max_color).as_bytes())?,
+                PpmFormat::Binary => writer.write_all(&Self::binary_row(row, max_color))?,
+            };
+        }
+
+        writer.flush()
+    }
+
+    fn ascii_row(row: &[Color], max_color: i32) -> String {
+        let mut row_data = String::new();
         let mut pixel_color = String::new();
-        for (pos, pixel) in self.pixels.iter().enumerate() {
-            let red = Self::scale_color(pixel.red, MAX_COLOR).to_string();
-            let green = Self::scale_color(pixel.green, MAX_COLOR).to_string();
-            let blue = Self::scale_color(pixel.blue, MAX_COLOR).to_string();
-
-            // Some programs don't work correctly if the line length 
-            // is greater than 70.
-            if pixel_color.len() + red.len() >= 70 {
-                pixel_color.pop();
-                pixel_color.push_str("\n");
-                ppm_data.push_str(pixel_color.as_str());
-                pixel_color.clear();
+        for pixel in row {
+            for channel in [pixel.red, pixel.green, pixel.blue].iter() {
+                let value = Self::scale_color(*channel, max_color).to_string();
+
+                // Some programs don't work correctly if the line length 
+                // is greater than 70.
+                if pixel_color.len() + value.len() >= 70 {
+                    pixel_color.pop();
+                    pixel_color.push_str("\n");
+                    row_data.push_str(pixel_color.as_str());
+                    pixel_color.clear();
+                }
+                pixel_color.push_str((value + " ").as_str());
             }
-            pixel_color.push_str((red + " ").as_str());
+        }
 
-            if pixel_color.len() + green.len() >= 70 {
-                pixel_color.pop();
-                pixel_color.push_str("\n");
-                ppm_data.push_str(pixel_color.as_str());
-                pixel_color.clear();
-            }
-            pixel_color.push_str((green + " ").as_str());
+        pixel_color.pop();
+        pixel_color.push_str("\n");
+        row_data.push_str(pixel_color.as_str());
 
-            if pixel_color.len() + blue.len() >= 70 {
-                pixel_color.pop();
-                pixel_color.push_str("\n");
-                ppm_data.push_str(pixel_color.as_str());
-                pixel_color.clear();
-            }
-            pixel_color.push_str((blue + " ").as_str());
+        row_data
+    }
 
-            if (pos + 1) % self.width == 0 {
-                pixel_color.pop();
-                pixel_color.push_str("\n");
-                ppm_data.push_str(pixel_color.as_str());
-                pixel_color.clear();
+    fn binary_row(row: &[Color], max_color: i32) -> Vec<u8> {
+        let mut row_data = vec![];
+        for pixel in row {
+            for channel in [pixel.red, pixel.green, pixel.blue].iter() {
+                let value = Self::scale_color(*channel, max_color);
+                if max_color > 255 {
+                    row_data.extend_from_slice(&(value as u16).to_be_bytes());
+                } else {
+                    row_data.push(value as u8);
+                }
             }
         }
 
-        ppm_data
+        row_data
+    }
+
+    /// Writes the canvas as a truecolor PNG image. Colors are clamped to
+    /// the 0-1 range and then encoded with the given transfer function, so
+    /// the canvas should hold linear colors, such as those tone mapped with
+    /// `TransferFunction::Linear`.
+    pub fn write_png<W: Write>(&self, writer: &mut W, bit_depth: PngBitDepth,
+        transfer: TransferFunction) -> io::Result<()> {
+        write_png(self, writer, bit_depth, transfer)
+    }
+
+    /// Writes the canvas as a Radiance RGBE image without clamping colors.
+    pub fn write_hdr<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_hdr(self, writer)
+    }
+
+    /// Writes the canvas as a Portable Float Map without clamping colors.
+    pub fn write_pfm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_pfm(self, writer)
     }
 
     fn scale_color(color_value: f64, max_color_value: i32) -> i32 {
         // Clamp color, if necessary.
         if color_value > 1.0 {
-            return 255;
+            return max_color_value;
         }
 
         if color_value < 0.0 {
@@ -198,4 +386,140 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn writing_png_to_sink() {
+        let canvas = Canvas::new(4, 4);
+        let mut data: Vec<u8> = vec![];
+
+        canvas.write_png(&mut data, PngBitDepth::Eight, TransferFunction::Srgb).unwrap();
+
+        assert_eq!(&[137, 80, 78, 71], &data[0..4]);
+    }
+
+    #[test]
+    fn streaming_ascii_ppm_matches_string_output() {
+        let mut canvas = Canvas::new(10, 2);
+        for i in 0..10 {
+            for j in 0..2 {
+                canvas.write_pixel(i, j, Color::new(1.0, 0.8, 0.6));
+            }
+        }
+        let mut data: Vec<u8> = vec![];
+
+        let expected = canvas.canvas_to_ppm();
+
+        canvas.write_ppm(&mut data, PpmFormat::Ascii, 255).unwrap();
+        let actual = String::from_utf8(data).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn constructing_binary_ppm() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, Color::new(1.5, 0.5, 0.));
+        canvas.write_pixel(1, 0, Color::new(-0.5, 0.2, 1.));
+        let mut data: Vec<u8> = vec![];
+
+        let mut expected = b"P6\n2 1\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 128, 0, 0, 51, 255]);
+
+        canvas.write_ppm(&mut data, PpmFormat::Binary, 255).unwrap();
+
+        assert_eq!(expected, data);
+    }
+
+    #[test]
+    fn constructing_sixteen_bit_binary_ppm() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.write_pixel(0, 0, Color::new(1., 0.5, 0.));
+        let mut data: Vec<u8> = vec![];
+
+        let mut expected = b"P6\n1 1\n65535\n".to_vec();
+        expected.extend_from_slice(&[255, 255, 128, 0, 0, 0]);
+
+        canvas.write_ppm(&mut data, PpmFormat::Binary, 65535).unwrap();
+
+        assert_eq!(expected, data);
+    }
+
+    #[test]
+    fn constructing_ascii_ppm_with_custom_maximum() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.write_pixel(0, 0, Color::new(2., 0.5, 0.));
+        let mut data: Vec<u8> = vec![];
+
+        let expected = String::from("P3\n1 1\n1023\n1023 512 0\n");
+
+        canvas.write_ppm(&mut data, PpmFormat::Ascii, 1023).unwrap();
+        let actual = String::from_utf8(data).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn reading_ascii_ppm_with_comments() {
+        let data = b"P3\n# A comment\n2 1 # trailing comment\n10\n10 5 0\n0 0\n10\n";
+
+        let expected_width = 2;
+        let expected_height = 1;
+        let expected_pixel1 = Color::new(1., 0.5, 0.);
+        let expected_pixel2 = Color::new(0., 0., 1.);
+
+        let actual = Canvas::from_ppm(data, TransferFunction::Linear).unwrap();
+
+        assert_eq!(expected_width, *actual.get_width());
+        assert_eq!(expected_height, *actual.get_height());
+        assert_eq!(expected_pixel1, actual.pixel_at(0, 0));
+        assert_eq!(expected_pixel2, actual.pixel_at(1, 0));
+    }
+
+    #[test]
+    fn reading_written_ppm_round_trips() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(1, 0, Color::new(1., 0.2, 0.6));
+        canvas.write_pixel(2, 1, Color::new(0.4, 0., 0.8));
+
+        for (format, max_color) in [(PpmFormat::Ascii, 255), (PpmFormat::Binary, 255),
+            (PpmFormat::Binary, 65535)].iter() {
+            let mut data: Vec<u8> = vec![];
+            canvas.write_ppm(&mut data, *format, *max_color).unwrap();
+
+            let actual = Canvas::from_ppm(&data, TransferFunction::Linear).unwrap();
+
+            for (expected_pixel, actual_pixel) in canvas.pixels.iter().zip(actual.pixels.iter()) {
+                assert_eq!(*expected_pixel, *actual_pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn reading_ppm_applies_transfer_function() {
+        let data = b"P3 1 1 255 188 0 255";
+
+        let expected = Color::new(TransferFunction::Srgb.decode(188. / 255.), 0., 1.);
+
+        let actual = Canvas::from_ppm(data, TransferFunction::Srgb).unwrap();
+
+        assert_eq!(expected, actual.pixel_at(0, 0));
+    }
+
+    #[test]
+    fn reading_malformed_ppm_returns_errors() {
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("unknown magic number P5"))),
+            Canvas::from_ppm(b"P5 1 1 255 0", TransferFunction::Linear).map(|_| ()));
+        assert_eq!(Err(ImageError::UnexpectedEnd),
+            Canvas::from_ppm(b"P3 2 1 255 0 0 0", TransferFunction::Linear).map(|_| ()));
+        assert_eq!(Err(ImageError::UnexpectedEnd),
+            Canvas::from_ppm(b"P6 2 1 255\n\x00\x00", TransferFunction::Linear).map(|_| ()));
+        assert_eq!(Err(ImageError::InvalidData(String::from("300 exceeds the maximum color value"))),
+            Canvas::from_ppm(b"P3 1 1 255 300 0 0", TransferFunction::Linear).map(|_| ()));
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("x is not a valid height"))),
+            Canvas::from_ppm(b"P3 1 x 255", TransferFunction::Linear).map(|_| ()));
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("18446744073709551615 by 2 image is too large"))),
+            Canvas::from_ppm(b"P6 18446744073709551615 2 255\n", TransferFunction::Linear).map(|_| ()));
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("100000 by 100000 image is too large"))),
+            Canvas::from_ppm(b"P6 100000 100000 255\n", TransferFunction::Linear).map(|_| ()));
+    }
+}
@@ -24,6 +24,41 @@ impl Color {
     }
 }
 
+/// The curve used to map linear color values to stored values.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TransferFunction {
+    Linear,
+    Srgb,
+}
+
+impl TransferFunction {
+    pub fn encode(&self, value: f64) -> f64 {
+        match self {
+            TransferFunction::Linear => value,
+            TransferFunction::Srgb => {
+                if value <= 0.0031308 {
+                    value * 12.92
+                } else {
+                    1.055 * value.powf(1. / 2.4) - 0.055
+                }
+            },
+        }
+    }
+
+    pub fn decode(&self, value: f64) -> f64 {
+        match self {
+            TransferFunction::Linear => value,
+            TransferFunction::Srgb => {
+                if value <= 0.04045 {
+                    value / 12.92
+                } else {
+                    ((value + 0.055) / 1.055).powf(2.4)
+                }
+            },
+        }
+    }
+}
+
 impl Add for Color {
     type Output = Color;
 
@@ -147,4 +182,32 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn srgb_transfer_function_round_trips() {
+        let transfer = TransferFunction::Srgb;
+
+        for value in [0., 0.001, 0.2, 0.5, 1.].iter() {
+            assert!(near_eq(*value, transfer.decode(transfer.encode(*value))));
+        }
+    }
+
+    #[test]
+    fn srgb_transfer_function_brightens_midtones() {
+        let transfer = TransferFunction::Srgb;
+
+        let expected = 0.73536;
+
+        let actual = transfer.encode(0.5);
+
+        assert!(near_eq(expected, actual));
+    }
+
+    #[test]
+    fn linear_transfer_function_leaves_values_alone() {
+        let transfer = TransferFunction::Linear;
+
+        assert!(near_eq(0.3, transfer.encode(0.3)));
+        assert!(near_eq(0.3, transfer.decode(0.3)));
+    }
+}
@@ -1,3 +1,4 @@
+use super::color::Color;
 use super::near_eq;
 use super::shape::Shape;
 use super::tuple::Tuple;
@@ -15,6 +16,12 @@ pub struct Computations {
     pub n1: f64,
     pub n2: f64,
     pub under_point: Tuple,
+    /// The light from the hit surviving absorption by the object the ray
+    /// travelled through to reach it.
+    pub attenuation: Color,
+    /// The wavelength of the ray that hit, which the refractive indices are
+    /// for.
+    pub wavelength: Option<f64>,
 }
 
 impl PartialEq for Computations {
@@ -24,6 +31,7 @@ impl PartialEq for Computations {
             self.normal_vector == other.normal_vector && self.inside == other.inside &&
             self.over_point == other.over_point && self.reflect_vector == other.reflect_vector &&
             near_eq(self.n1, other.n1) && near_eq(self.n2, other.n2) &&
-            self.under_point == other.under_point
+            self.under_point == other.under_point && self.attenuation == other.attenuation &&
+            self.wavelength == other.wavelength
     }    
 }
\ No newline at end of file
@@ -78,11 +78,10 @@ impl CSG {
     }
 
     pub fn update_csg_reference(csg: Self) {
-        let read_reference = PARENT_REFERENCES.read().unwrap();
-        let index = read_reference.iter().position(|pr| pr.get_id() == *csg.get_id()); 
-        drop(read_reference);
-
+        // Look up the index while holding the write lock so that concurrent
+        // updates cannot insert the same reference twice.
         let mut write_reference = PARENT_REFERENCES.write().unwrap();
+        let index = write_reference.iter().position(|pr| pr.get_id() == *csg.get_id());
         match index {
             Some(i) => {
                 write_reference.remove(i);
@@ -69,11 +69,10 @@ impl Group {
     }
 
     pub fn update_group_reference(group: Self) {
-        let read_reference = PARENT_REFERENCES.read().unwrap();
-        let index = read_reference.iter().position(|pr| pr.get_id() == *group.get_id()); 
-        drop(read_reference);
-
+        // Look up the index while holding the write lock so that concurrent
+        // updates cannot insert the same reference twice.
         let mut write_reference = PARENT_REFERENCES.write().unwrap();
+        let index = write_reference.iter().position(|pr| pr.get_id() == *group.get_id());
         match index {
             Some(i) => {
                 write_reference.remove(i);
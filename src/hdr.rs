@@ -0,0 +1,468 @@
+use super::canvas::{Canvas, ImageError};
+use super::color::Color;
+use std::io::{self, Write};
+
+// Scanlines between these widths use the run-length encoded RGBE format.
+const MINIMUM_RLE_WIDTH: usize = 8;
+const MAXIMUM_RLE_WIDTH: usize = 0x7fff;
+
+/// Writes the canvas as a Radiance RGBE (.hdr) image with run-length encoded
+/// scanlines. Colors are stored with a shared exponent, so values above 1.0
+/// survive the trip.
+pub fn write_hdr<W: Write>(canvas: &Canvas, writer: &mut W) -> io::Result<()> {
+    let width = *canvas.get_width();
+    let height = *canvas.get_height();
+
+    write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+    for y in 0..height {
+        let scanline = (0..width).map(|x| color_to_rgbe(canvas.pixel_at(x as u32, y as u32)))
+            .collect::<Vec<[u8; 4]>>();
+
+        if !(MINIMUM_RLE_WIDTH..=MAXIMUM_RLE_WIDTH).contains(&width) {
+            for rgbe in scanline {
+                writer.write_all(&rgbe)?;
+            }
+            continue;
+        }
+
+        writer.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+        for channel in 0..4 {
+            let values = scanline.iter().map(|rgbe| rgbe[channel]).collect::<Vec<u8>>();
+            writer.write_all(&run_length_encode(&values))?;
+        }
+    }
+
+    writer.flush()
+}
+
+pub fn read_hdr(data: &[u8]) -> Result<Canvas, ImageError> {
+    let mut position = 0;
+    let first_line = read_line(data, &mut position)?;
+    if !first_line.starts_with("#?") {
+        return Err(ImageError::InvalidHeader(String::from("missing Radiance signature")));
+    }
+
+    loop {
+        let line = read_line(data, &mut position)?;
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(ImageError::Unsupported(format!("{} format", format)));
+            }
+        }
+    }
+
+    let resolution = read_line(data, &mut position)?;
+    let fields = resolution.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() != 4 || fields[0] != "-Y" || fields[2] != "+X" {
+        return Err(ImageError::Unsupported(format!("resolution {}", resolution)));
+    }
+    let parse = |field: &str| field.parse::<usize>().map_err(|_| {
+        ImageError::InvalidHeader(format!("{} is not a valid dimension", field))
+    });
+    let (height, width) = (parse(fields[1])?, parse(fields[3])?);
+    Canvas::checked_pixel_count(width, height)?;
+
+    let mut canvas = Canvas::new(width, height);
+    for y in 0..height {
+        let scanline = read_scanline(data, &mut position, width)?;
+        for (x, rgbe) in scanline.into_iter().enumerate() {
+            canvas.write_pixel(x as u32, y as u32, rgbe_to_color(rgbe));
+        }
+    }
+
+    Ok(canvas)
+}
+
+/// Writes the canvas as a little-endian Portable Float Map, which stores each
+/// channel as a 32-bit float with rows ordered from bottom to top.
+pub fn write_pfm<W: Write>(canvas: &Canvas, writer: &mut W) -> io::Result<()> {
+    let width = *canvas.get_width();
+    let height = *canvas.get_height();
+
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    for y in (0..height).rev() {
+        let mut row = Vec::with_capacity(width * 12);
+        for x in 0..width {
+            let pixel = canvas.pixel_at(x as u32, y as u32);
+            for channel in [pixel.red, pixel.green, pixel.blue].iter() {
+                row.extend_from_slice(&(*channel as f32).to_le_bytes());
+            }
+        }
+        writer.write_all(&row)?;
+    }
+
+    writer.flush()
+}
+
+pub fn read_pfm(data: &[u8]) -> Result<Canvas, ImageError> {
+    let mut position = 0;
+    let mut tokens = vec![];
+    while tokens.len() < 4 {
+        while position < data.len() && data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+
+        let start = position;
+        while position < data.len() && !data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+
+        if start == position {
+            return Err(ImageError::UnexpectedEnd);
+        }
+        tokens.push(String::from_utf8_lossy(&data[start..position]).to_string());
+    }
+    // A single whitespace character precedes the raster.
+    position += 1;
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(ImageError::InvalidHeader(format!("unknown magic number {}", tokens[0]))),
+    };
+    let parse = |token: &str| token.parse::<usize>().map_err(|_| {
+        ImageError::InvalidHeader(format!("{} is not a valid dimension", token))
+    });
+    let (width, height) = (parse(&tokens[1])?, parse(&tokens[2])?);
+    let scale = tokens[3].parse::<f64>().map_err(|_| {
+        ImageError::InvalidHeader(format!("{} is not a valid scale", tokens[3]))
+    })?;
+    if scale == 0. {
+        return Err(ImageError::InvalidHeader(String::from("scale must not be zero")));
+    }
+
+    let end = position + Canvas::checked_pixel_count(width, height)? * channels * 4;
+    if data.len() < end {
+        return Err(ImageError::UnexpectedEnd);
+    }
+
+    // A negative scale marks little-endian data.
+    let values = data[position..end].chunks(4).map(|bytes| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if scale < 0. { f32::from_le_bytes(bytes) as f64 } else { f32::from_be_bytes(bytes) as f64 }
+    }).collect::<Vec<f64>>();
+
+    let mut canvas = Canvas::new(width, height);
+    for (index, pixel) in values.chunks(channels).enumerate() {
+        let x = index % width.max(1);
+        let y = height - 1 - index / width.max(1);
+        let color = if channels == 3 {
+            Color::new(pixel[0], pixel[1], pixel[2])
+        } else {
+            Color::new(pixel[0], pixel[0], pixel[0])
+        };
+        canvas.write_pixel(x as u32, y as u32, color);
+    }
+
+    Ok(canvas)
+}
+
+pub fn color_to_rgbe(color: Color) -> [u8; 4] {
+    let brightest = color.red.max(color.green).max(color.blue);
+    if brightest < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // Split the brightest channel into a mantissa in [0.5, 1) and an exponent.
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    let mut mantissa = brightest / 2_f64.powi(exponent);
+    if mantissa >= 1. {
+        exponent += 1;
+        mantissa /= 2.;
+    } else if mantissa < 0.5 {
+        exponent -= 1;
+        mantissa *= 2.;
+    }
+
+    let scale = mantissa * 256. / brightest;
+    let channel = |value: f64| (value.max(0.) * scale).min(255.) as u8;
+
+    [channel(color.red), channel(color.green), channel(color.blue), (exponent + 128) as u8]
+}
+
+pub fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0., 0., 0.);
+    }
+
+    let factor = 2_f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new((rgbe[0] as f64 + 0.5) * factor, (rgbe[1] as f64 + 0.5) * factor,
+        (rgbe[2] as f64 + 0.5) * factor)
+}
+
+// Encodes runs of four or more identical bytes, emitting everything else as
+// literal dumps of up to 128 bytes.
+fn run_length_encode(values: &[u8]) -> Vec<u8> {
+    const MINIMUM_RUN: usize = 4;
+    let mut encoded = vec![];
+    let mut position = 0;
+
+    while position < values.len() {
+        let mut run_start = position;
+        let mut run_length = 0;
+        while run_start < values.len() {
+            run_length = values[run_start..].iter().take(127)
+                .take_while(|v| **v == values[run_start]).count();
+            if run_length >= MINIMUM_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        if run_length < MINIMUM_RUN {
+            run_start = values.len();
+        }
+
+        while position < run_start {
+            let count = (run_start - position).min(128);
+            encoded.push(count as u8);
+            encoded.extend_from_slice(&values[position..position + count]);
+            position += count;
+        }
+
+        if run_start < values.len() {
+            encoded.push(128 + run_length as u8);
+            encoded.push(values[run_start]);
+            position = run_start + run_length;
+        }
+    }
+
+    encoded
+}
+
+fn read_scanline(data: &[u8], position: &mut usize, width: usize) -> Result<Vec<[u8; 4]>, ImageError> {
+    let remaining = &data[*position..];
+    let run_length_encoded = (MINIMUM_RLE_WIDTH..=MAXIMUM_RLE_WIDTH).contains(&width) &&
+        remaining.len() >= 4 && remaining[0] == 2 && remaining[1] == 2 && remaining[2] & 0x80 == 0;
+
+    if !run_length_encoded {
+        let length = width.checked_mul(4).ok_or_else(|| {
+            ImageError::InvalidHeader(format!("scanline width {} is too large", width))
+        })?;
+        if remaining.len() < length {
+            return Err(ImageError::UnexpectedEnd);
+        }
+
+        *position += length;
+        return Ok(remaining[..length].chunks(4).map(|c| [c[0], c[1], c[2], c[3]]).collect());
+    }
+
+    if ((remaining[2] as usize) << 8 | remaining[3] as usize) != width {
+        return Err(ImageError::InvalidData(String::from("scanline width mismatch")));
+    }
+    *position += 4;
+
+    let mut scanline = vec![[0; 4]; width];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(*position).ok_or(ImageError::UnexpectedEnd)? as usize;
+            *position += 1;
+
+            if count > 128 {
+                let value = *data.get(*position).ok_or(ImageError::UnexpectedEnd)?;
+                *position += 1;
+                if x + count - 128 > width {
+                    return Err(ImageError::InvalidData(String::from("run overflows scanline")));
+                }
+                for pixel in &mut scanline[x..x + count - 128] {
+                    pixel[channel] = value;
+                }
+                x += count - 128;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(ImageError::InvalidData(String::from("bad scanline data")));
+                }
+                if data.len() < *position + count {
+                    return Err(ImageError::UnexpectedEnd);
+                }
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(&data[*position..*position + count]) {
+                    pixel[channel] = *value;
+                }
+                *position += count;
+                x += count;
+            }
+        }
+    }
+
+    Ok(scanline)
+}
+
+fn read_line(data: &[u8], position: &mut usize) -> Result<String, ImageError> {
+    let start = *position;
+    while *position < data.len() && data[*position] != b'\n' {
+        *position += 1;
+    }
+
+    if *position >= data.len() {
+        return Err(ImageError::UnexpectedEnd);
+    }
+    *position += 1;
+
+    Ok(String::from_utf8_lossy(&data[start..*position - 1]).trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RGBE precision is relative to the brightest channel of each pixel.
+    fn assert_close(expected: Color, actual: Color, tolerance: f64) {
+        let limit = tolerance * expected.red.max(expected.green).max(expected.blue).max(1.);
+
+        assert!((expected.red - actual.red).abs() <= limit);
+        assert!((expected.green - actual.green).abs() <= limit);
+        assert!((expected.blue - actual.blue).abs() <= limit);
+    }
+
+    #[test]
+    fn rgbe_encoding_shares_exponent() {
+        let expected = [128, 64, 0, 129];
+
+        let actual = color_to_rgbe(Color::new(1., 0.5, 0.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rgbe_round_trips_bright_colors() {
+        let color = Color::new(12.5, 0.75, 3.);
+
+        let actual = rgbe_to_color(color_to_rgbe(color));
+
+        assert_close(color, actual, 0.05);
+        assert!(actual.red > 12.);
+    }
+
+    #[test]
+    fn black_encodes_to_zero_exponent() {
+        let expected = [0, 0, 0, 0];
+
+        let actual = color_to_rgbe(Color::new(0., 0., 0.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn run_length_encoding_compresses_runs() {
+        let values = [5, 5, 5, 5, 5, 1, 2, 3, 9, 9, 9, 9];
+
+        let expected = vec![133, 5, 3, 1, 2, 3, 132, 9];
+
+        let actual = run_length_encode(&values);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn writing_and_reading_hdr_round_trips() {
+        for width in [3, 20].iter() {
+            let mut canvas = Canvas::new(*width, 2);
+            canvas.write_pixel(1, 0, Color::new(5., 2.5, 0.1));
+            canvas.write_pixel(2, 1, Color::new(0.2, 0.4, 100.));
+            let mut data = vec![];
+            write_hdr(&canvas, &mut data).unwrap();
+
+            let actual = read_hdr(&data).unwrap();
+
+            assert_eq!(*width, *actual.get_width());
+            assert_eq!(2, *actual.get_height());
+            for y in 0..2 {
+                for x in 0..*width as u32 {
+                    assert_close(canvas.pixel_at(x, y), actual.pixel_at(x, y), 0.01);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hdr_header_describes_image() {
+        let canvas = Canvas::new(10, 4);
+        let mut data = vec![];
+        write_hdr(&canvas, &mut data).unwrap();
+
+        let expected = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 4 +X 10\n";
+
+        let actual = String::from_utf8_lossy(&data[0..expected.len()]);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn reading_malformed_hdr_returns_errors() {
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("missing Radiance signature"))),
+            read_hdr(b"P3\n").map(|_| ()));
+        assert_eq!(Err(ImageError::Unsupported(String::from("32-bit_rle_xyze format"))),
+            read_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n").map(|_| ()));
+        assert_eq!(Err(ImageError::UnexpectedEnd),
+            read_hdr(b"#?RADIANCE\n\n-Y 1 +X 2\n\x80\x80\x80\x81").map(|_| ()));
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("100000 by 100000 image is too large"))),
+            read_hdr(b"#?RADIANCE\n\n-Y 100000 +X 100000\n").map(|_| ()));
+    }
+
+    #[test]
+    fn writing_and_reading_pfm_round_trips() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(0, 0, Color::new(7.25, -1., 0.5));
+        canvas.write_pixel(2, 1, Color::new(0.125, 1000., 3.));
+        let mut data = vec![];
+        write_pfm(&canvas, &mut data).unwrap();
+
+        let actual = read_pfm(&data).unwrap();
+
+        for y in 0..2 {
+            for x in 0..3 {
+                assert_eq!(canvas.pixel_at(x, y), actual.pixel_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn pfm_rows_are_stored_bottom_to_top() {
+        let mut canvas = Canvas::new(1, 2);
+        canvas.write_pixel(0, 1, Color::new(2., 0., 0.));
+        let mut data = vec![];
+        write_pfm(&canvas, &mut data).unwrap();
+
+        let header = b"PF\n1 2\n-1.0\n";
+
+        assert_eq!(header, &data[0..header.len()]);
+        assert_eq!(2_f32.to_le_bytes(), data[header.len()..header.len() + 4]);
+    }
+
+    #[test]
+    fn reading_big_endian_grayscale_pfm() {
+        let mut data = b"Pf\n2 1\n1.0\n".to_vec();
+        data.extend_from_slice(&0.5_f32.to_be_bytes());
+        data.extend_from_slice(&4_f32.to_be_bytes());
+
+        let actual = read_pfm(&data).unwrap();
+
+        assert_eq!(Color::new(0.5, 0.5, 0.5), actual.pixel_at(0, 0));
+        assert_eq!(Color::new(4., 4., 4.), actual.pixel_at(1, 0));
+    }
+
+    #[test]
+    fn reading_oversized_pfm_returns_error() {
+        let expected = Err(ImageError::InvalidHeader(String::from("4611686018427387904 by 4 image is too large")));
+
+        let actual = read_pfm(b"PF\n4611686018427387904 4\n-1.0\n").map(|_| ());
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn reading_truncated_pfm_returns_error() {
+        let expected = Err(ImageError::UnexpectedEnd);
+
+        let actual = read_pfm(b"PF\n2 2\n-1.0\n\x00\x00").map(|_| ());
+
+        assert_eq!(expected, actual);
+    }
+}
@@ -0,0 +1,798 @@
+use super::BLACK;
+use super::color::Color;
+use super::computations::Computations;
+use super::DEFAULT_RECURSION;
+use super::intersection::Intersection;
+use super::light::{direction_to, Light, LightPosition, LightTrait};
+use super::material::Material;
+use super::random::Rng;
+use super::ray::Ray;
+use super::sampling::{cosine_hemisphere, uniform_cone};
+use super::shape::CommonShape;
+use super::tuple::Tuple;
+use super::WHITE;
+use super::world::World;
+use std::f64::consts::PI;
+
+/// How the camera turns a ray into a color.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Integrator {
+    /// Direct lighting plus mirror reflection and refraction, as traced by
+    /// `World::color_at`.
+    #[default]
+    Whitted,
+    /// Unbiased Monte Carlo path tracing.
+    PathTracer (PathTracing),
+    /// Path tracing that also follows light from the lights, for scenes lit
+    /// mostly indirectly.
+    Bidirectional (BidirectionalPathTracing),
+}
+
+impl Integrator {
+    pub fn radiance(&self, world: &World, ray: Ray, rng: &mut Rng) -> Color {
+        match self {
+            Integrator::Whitted => world.color_at(ray, DEFAULT_RECURSION),
+            Integrator::PathTracer(settings) => settings.radiance(world, ray, rng),
+            Integrator::Bidirectional(settings) => settings.radiance(world, ray, rng),
+        }
+    }
+}
+
+/// Settings for the path tracer. Paths longer than `russian_roulette_depth`
+/// are terminated at random in proportion to how little they can still
+/// contribute, and no path is longer than `max_depth`.
+///
+/// Materials are interpreted as a mix of a Lambertian lobe weighted by
+/// `diffuse`, a mirror lobe weighted by `reflective` and a refractive lobe
+/// weighted by `transparency`. Light sources are sampled directly at every
+/// bounce with the same Lambertian lobe, so `ambient`, `specular` and
+/// `shininess` are ignored.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PathTracing {
+    pub max_depth: u32,
+    pub russian_roulette_depth: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Lobe {
+    Diffuse,
+    Reflect,
+    Transmit,
+}
+
+impl PathTracing {
+    pub fn new(max_depth: u32, russian_roulette_depth: u32) -> Self {
+        Self { max_depth, russian_roulette_depth }
+    }
+
+    pub fn radiance(&self, world: &World, ray: Ray, rng: &mut Rng) -> Color {
+        let mut radiance = BLACK;
+        let mut throughput = WHITE;
+        let mut ray = ray;
+        // Emission (and environment light) found by a diffuse bounce was
+        // already counted when the light was sampled directly, even if the
+        // path was then refracted, since shadow rays pass through
+        // transparent objects.
+        let mut count_light_emission = true;
+
+        for depth in 0..self.max_depth {
+            let intersections = world.intersect_world(ray);
+            let hit = match Intersection::hit(intersections.clone()) {
+                Some(hit) => hit,
+                None => {
+                    if count_light_emission || !world.samples_environment() {
+                        radiance += throughput * world.background.color_in(ray.direction);
+                    }
+                    break;
+                },
+            };
+
+            let computations = hit.prepare_computations(ray, intersections);
+            let object = computations.object.clone();
+            let material = object.get_material();
+            throughput = throughput * computations.attenuation;
+
+            let is_light = world.lights.iter().any(|light| light.emitter_id() == Some(object.get_id()));
+            if count_light_emission || !is_light {
+                radiance += throughput * material.emission;
+            }
+
+            radiance += throughput * world.direct_lighting(&computations);
+
+            let (lobe, total) = match choose_lobe(&computations, &material, rng) {
+                Some(choice) => choice,
+                None => break,
+            };
+
+            // Each lobe is picked with probability weight / total, so scaling
+            // by the total keeps the estimate unbiased.
+            throughput = match lobe {
+                Lobe::Diffuse => throughput * material.color_at(object, computations.over_point) * total,
+                _ => throughput * total,
+            };
+            ray = scattered_ray(lobe, &computations, rng);
+            let refracted = ray.direction.dot(computations.normal_vector) < 0.;
+            count_light_emission = match lobe {
+                Lobe::Diffuse => false,
+                Lobe::Transmit if refracted => count_light_emission,
+                _ => true,
+            };
+
+            if depth + 1 >= self.russian_roulette_depth {
+                let survival = throughput.red.max(throughput.green).max(throughput.blue).clamp(0.05, 0.95);
+                if rng.next_f64() >= survival {
+                    break;
+                }
+
+                throughput = throughput * (1. / survival);
+            }
+        }
+
+        radiance
+    }
+}
+
+impl Default for PathTracing {
+    fn default() -> Self {
+        Self::new(16, 3)
+    }
+}
+
+/// Settings for the bidirectional path tracer. Each camera ray starts an eye
+/// subpath and each light starts a light subpath, both at most `max_depth`
+/// vertices long. Every eye vertex is connected to the light and to every
+/// light vertex, and the connections are combined with multiple importance
+/// sampling using the power heuristic.
+///
+/// Surfaces are treated as Lambertian with mirror and refractive lobes, so
+/// `ambient`, `specular` and `shininess` are ignored. Light vertices are never
+/// connected to the camera. Light through transparent objects is filtered by
+/// the connections instead, as in the other integrators, so light subpaths
+/// end when refracted. Directional lights and environment maps are only
+/// sampled from eye vertices.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BidirectionalPathTracing {
+    pub max_depth: u32,
+    pub russian_roulette_depth: u32,
+}
+
+// A scattering event on a subpath. The densities are with respect to area:
+// `pdf_forward` for reaching the vertex along its own subpath, and
+// `pdf_reverse` for the other subpath reaching it from the following vertex.
+// Mirror and refractive bounces have a density of 1 in both directions, since
+// they cancel out of every strategy that can produce the path.
+#[derive(Debug, Clone)]
+struct Vertex {
+    computations: Computations,
+    material: Material,
+    throughput: Color,
+    delta: bool,
+    pdf_forward: f64,
+    pdf_reverse: f64,
+}
+
+impl Vertex {
+    fn point(&self) -> Tuple {
+        self.computations.point
+    }
+
+    fn normal(&self) -> Tuple {
+        self.computations.normal_vector
+    }
+
+    fn lambert(&self) -> Color {
+        let color = self.material.color_at(self.computations.object.clone(), self.computations.point);
+
+        color * (self.material.diffuse / PI)
+    }
+
+    // The density over directions of scattering diffusely toward `direction`.
+    // The lobe is assumed to be picked by its share of the fixed lobe weights,
+    // so that both subpaths agree on it.
+    fn diffuse_pdf(&self, direction: Tuple) -> f64 {
+        let total = self.material.diffuse + self.material.reflective + self.material.transparency;
+        if total <= 0. {
+            return 0.;
+        }
+
+        (self.material.diffuse / total) * direction.dot(self.normal()).max(0.) / PI
+    }
+
+    // The density over area at `to` of scattering diffusely toward it.
+    fn diffuse_pdf_to(&self, to: &Vertex) -> f64 {
+        area_density(self.diffuse_pdf((to.point() - self.point()).normalize()), self.point(), to)
+    }
+}
+
+impl BidirectionalPathTracing {
+    pub fn new(max_depth: u32, russian_roulette_depth: u32) -> Self {
+        Self { max_depth, russian_roulette_depth }
+    }
+
+    pub fn radiance(&self, world: &World, ray: Ray, rng: &mut Rng) -> Color {
+        let (eye, mut radiance) = self.walk(world, ray, None, None, rng);
+        if eye.is_empty() {
+            return radiance;
+        }
+
+        for light in world.lights.iter() {
+            let samples = match light.get_position() {
+                LightPosition::Point(center) => light.sample_points(center),
+                infinite => vec![infinite],
+            };
+            let sample = samples[((rng.next_f64() * samples.len() as f64) as usize).min(samples.len() - 1)];
+
+            let position = match sample {
+                LightPosition::Point(position) => position,
+                // Lights infinitely far away can only be reached from the eye.
+                LightPosition::Infinite(_) => {
+                    for vertex in eye.iter() {
+                        radiance += self.connect_to_light(world, light, sample, vertex);
+                    }
+                    continue;
+                },
+            };
+
+            let direction = uniform_cone(Tuple::vector(0., 1., 0.), -1., rng.next_f64(), rng.next_f64());
+            let light_ray = Ray::new(position, direction).with_wavelength(ray.wavelength);
+            let (light_path, _) = self.walk(world, light_ray, Some(1. / (4. * PI)), Some(light), rng);
+
+            for eye_length in 1..=eye.len() {
+                let eye_path = &eye[..eye_length];
+                let contribution = self.connect_to_light(world, light, sample, &eye_path[eye_length - 1]);
+                if contribution != BLACK {
+                    radiance += contribution * Self::mis_weight(position, &[], eye_path);
+                }
+
+                for light_length in 1..=light_path.len() {
+                    let connected = &light_path[..light_length];
+                    let contribution = Self::connect(world, &connected[light_length - 1], &eye_path[eye_length - 1]);
+                    if contribution != BLACK {
+                        radiance += contribution * Self::mis_weight(position, connected, eye_path);
+                    }
+                }
+            }
+        }
+
+        radiance
+    }
+
+    // Follows a subpath from the eye, or from the light if one is given, and
+    // returns its vertices. Eye subpaths also return the emission and
+    // background they find along the way.
+    fn walk(&self, world: &World, ray: Ray, pdf: Option<f64>, light: Option<&Light>, rng: &mut Rng)
+        -> (Vec<Vertex>, Color) {
+        let mut vertices: Vec<Vertex> = vec![];
+        let mut radiance = BLACK;
+        let mut throughput = WHITE;
+        let mut ray = ray;
+        let mut pdf = pdf;
+        let mut count_light_emission = true;
+        let emitter = light.and_then(|light| light.emitter_id());
+
+        for depth in 0..self.max_depth {
+            let intersections = world.intersect_world(ray);
+            let hit = match intersections.iter().find(|i| i.t > 0. && Some(i.object.get_id()) != emitter) {
+                Some(hit) => hit.clone(),
+                None => {
+                    if light.is_none() && (count_light_emission || !world.samples_environment()) {
+                        radiance += throughput * world.background.color_in(ray.direction);
+                    }
+                    break;
+                },
+            };
+
+            let computations = hit.prepare_computations(ray, intersections);
+            let object = computations.object.clone();
+            let material = object.get_material();
+
+            match light {
+                Some(light) if depth == 0 => {
+                    if !light.get_linking().illuminates(&World::object_ids(&object)) {
+                        break;
+                    }
+
+                    // Lights here do not fall off with distance, and each
+                    // photon covers 4π of the light's sphere of directions.
+                    let distance = hit.t * ray.direction.magnitude();
+                    throughput = light.illumination_at(computations.point) * computations.attenuation *
+                        (4. * PI * PI * distance * distance);
+                    if throughput == BLACK {
+                        break;
+                    }
+                },
+                Some(_) => throughput = throughput * computations.attenuation,
+                None => {
+                    throughput = throughput * computations.attenuation;
+                    let is_light = world.lights.iter().any(|light| light.emitter_id() == Some(object.get_id()));
+                    if count_light_emission || !is_light {
+                        radiance += throughput * material.emission;
+                    }
+                    radiance += throughput * world.environment_lighting(&computations);
+                },
+            }
+
+            let choice = choose_lobe(&computations, &material, rng);
+            let mut vertex = Vertex {
+                computations: computations.clone(),
+                material: material.clone(),
+                throughput,
+                delta: matches!(choice, Some((Lobe::Reflect, _)) | Some((Lobe::Transmit, _))),
+                pdf_forward: 1.,
+                pdf_reverse: 0.,
+            };
+            if let Some(pdf) = pdf {
+                vertex.pdf_forward = area_density(pdf, ray.origin, &vertex);
+            }
+            vertices.push(vertex);
+
+            let (lobe, total) = match choice {
+                Some(choice) => choice,
+                None => break,
+            };
+            let next_ray = scattered_ray(lobe, &computations, rng);
+            let refracted = next_ray.direction.dot(computations.normal_vector) < 0.;
+            if light.is_some() && refracted {
+                break;
+            }
+
+            // Light subpaths cannot pass through a refraction, so neither can
+            // they reach the vertex before one on an eye subpath.
+            let count = vertices.len();
+            if count >= 2 {
+                let reverse = match lobe {
+                    Lobe::Diffuse => vertices[count - 1].diffuse_pdf_to(&vertices[count - 2]),
+                    _ if refracted => 0.,
+                    _ => 1.,
+                };
+                vertices[count - 2].pdf_reverse = reverse;
+            }
+
+            throughput = match lobe {
+                Lobe::Diffuse => throughput * material.color_at(object, computations.over_point) * total,
+                _ => throughput * total,
+            };
+            pdf = match lobe {
+                Lobe::Diffuse => Some(vertices[count - 1].diffuse_pdf(next_ray.direction)),
+                _ => None,
+            };
+            count_light_emission = lobe != Lobe::Diffuse;
+            ray = next_ray;
+
+            if depth + 1 >= self.russian_roulette_depth {
+                let survival = throughput.red.max(throughput.green).max(throughput.blue).clamp(0.05, 0.95);
+                if rng.next_f64() >= survival {
+                    break;
+                }
+
+                throughput = throughput * (1. / survival);
+            }
+        }
+
+        (vertices, radiance)
+    }
+
+    // Light reaching the end of an eye subpath straight from a point on the
+    // light, matching the diffuse term of `Material::lighting`.
+    fn connect_to_light(&self, world: &World, light: &Light, position: LightPosition, vertex: &Vertex) -> Color {
+        if vertex.material.diffuse <= 0. ||
+            !light.get_linking().illuminates(&World::object_ids(&vertex.computations.object)) {
+            return BLACK;
+        }
+
+        let point = vertex.computations.over_point;
+        let (direction, _) = direction_to(position, point);
+        let cosine = direction.dot(vertex.normal());
+        let illumination = light.illumination_at(point);
+        if cosine <= 0. || illumination == BLACK {
+            return BLACK;
+        }
+
+        let transmittance = if light.get_linking().casts_shadows {
+            world.transmittance_ignoring(point, position, light.emitter_id())
+        } else {
+            WHITE
+        };
+
+        vertex.throughput * vertex.lambert() * illumination * transmittance * (PI * cosine)
+    }
+
+    // Light carried from the end of a light subpath to the end of an eye
+    // subpath.
+    fn connect(world: &World, light_end: &Vertex, eye_end: &Vertex) -> Color {
+        if light_end.material.diffuse <= 0. || eye_end.material.diffuse <= 0. {
+            return BLACK;
+        }
+
+        let offset = eye_end.point() - light_end.point();
+        let distance_squared = offset.dot(offset);
+        let direction = offset.normalize();
+        let light_cosine = direction.dot(light_end.normal());
+        let eye_cosine = -direction.dot(eye_end.normal());
+        if light_cosine <= 0. || eye_cosine <= 0. {
+            return BLACK;
+        }
+
+        let transmittance = world.transmittance_from(eye_end.computations.over_point,
+            LightPosition::Point(light_end.computations.over_point));
+
+        light_end.throughput * light_end.lambert() * eye_end.lambert() * eye_end.throughput * transmittance *
+            (light_cosine * eye_cosine / distance_squared)
+    }
+
+    // The power heuristic weight of connecting the given subpaths, compared
+    // with every other split of the same path between the light and the eye.
+    fn mis_weight(light_position: Tuple, light_path: &[Vertex], eye_path: &[Vertex]) -> f64 {
+        let eye_end = &eye_path[eye_path.len() - 1];
+        let mut eye_reverse = eye_path.iter().map(|vertex| vertex.pdf_reverse).collect::<Vec<f64>>();
+        let mut light_reverse = light_path.iter().map(|vertex| vertex.pdf_reverse).collect::<Vec<f64>>();
+
+        // The vertices at the connection scatter diffusely, whatever their
+        // subpaths did next.
+        let last = eye_reverse.len() - 1;
+        eye_reverse[last] = match light_path.last() {
+            Some(light_end) => light_end.diffuse_pdf_to(eye_end),
+            None => area_density(1. / (4. * PI), light_position, eye_end),
+        };
+        if last >= 1 {
+            eye_reverse[last - 1] = eye_end.diffuse_pdf_to(&eye_path[last - 1]);
+        }
+        if let Some(light_end) = light_path.last() {
+            let last = light_reverse.len() - 1;
+            light_reverse[last] = eye_end.diffuse_pdf_to(light_end);
+            if last >= 1 {
+                light_reverse[last - 1] = light_end.diffuse_pdf_to(&light_path[last - 1]);
+            }
+        }
+
+        let ratio = |reverse: f64, forward: f64| if forward > 0. { reverse / forward } else { 0. };
+        let mut sum = 0.;
+
+        // Moving the connection toward the eye, short of the camera itself.
+        let mut relative = 1.;
+        for i in (1..eye_path.len()).rev() {
+            relative *= ratio(eye_reverse[i], eye_path[i].pdf_forward);
+            let delta = i != eye_path.len() - 1 && eye_path[i].delta;
+            if !delta && !eye_path[i - 1].delta {
+                sum += relative * relative;
+            }
+        }
+
+        // Moving the connection toward the light, short of hitting the light.
+        let mut relative = 1.;
+        for i in (0..light_path.len()).rev() {
+            relative *= ratio(light_reverse[i], light_path[i].pdf_forward);
+            let delta = i != light_path.len() - 1 && light_path[i].delta;
+            let previous_delta = i > 0 && light_path[i - 1].delta;
+            if !delta && !previous_delta {
+                sum += relative * relative;
+            }
+        }
+
+        1. / (1. + sum)
+    }
+}
+
+impl Default for BidirectionalPathTracing {
+    fn default() -> Self {
+        Self::new(8, 3)
+    }
+}
+
+// Picks a lobe in proportion to its weight, returning it along with the total
+// weight of all lobes, or `None` if the surface scatters no light.
+fn choose_lobe(computations: &Computations, material: &Material, rng: &mut Rng) -> Option<(Lobe, f64)> {
+    let (reflect_weight, transmit_weight) = if material.reflective > 0. && material.transparency > 0. {
+        let reflectance = Intersection::schlick(computations.clone());
+
+        (material.reflective * reflectance, material.transparency * (1. - reflectance))
+    } else {
+        (material.reflective, material.transparency)
+    };
+    let diffuse_weight = material.diffuse;
+    let total = diffuse_weight + reflect_weight + transmit_weight;
+    if total <= 0. {
+        return None;
+    }
+
+    let choice = rng.next_f64() * total;
+    let lobe = if choice < diffuse_weight {
+        Lobe::Diffuse
+    } else if choice < diffuse_weight + reflect_weight {
+        Lobe::Reflect
+    } else {
+        Lobe::Transmit
+    };
+
+    Some((lobe, total))
+}
+
+// Total internal reflection turns a refraction into a reflection.
+fn scattered_ray(lobe: Lobe, computations: &Computations, rng: &mut Rng) -> Ray {
+    let ray = match lobe {
+        Lobe::Diffuse => {
+            let direction = cosine_hemisphere(computations.normal_vector, rng.next_f64(), rng.next_f64());
+
+            Ray::new(computations.over_point, direction)
+        },
+        Lobe::Reflect => Ray::new(computations.over_point, computations.reflect_vector),
+        Lobe::Transmit => match World::refracted_direction(computations) {
+            Some(direction) => Ray::new(computations.under_point, direction),
+            None => Ray::new(computations.over_point, computations.reflect_vector),
+        },
+    };
+
+    ray.with_wavelength(computations.wavelength)
+}
+
+// Converts a density over directions leaving `from` into a density over area
+// at the vertex.
+fn area_density(pdf: f64, from: Tuple, to: &Vertex) -> f64 {
+    let offset = to.point() - from;
+    let distance_squared = offset.dot(offset);
+    if distance_squared <= 0. {
+        return 0.;
+    }
+
+    pdf * offset.normalize().dot(to.normal()).abs() / distance_squared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::background::Background;
+    use super::super::light::Light;
+    use super::super::material::Material;
+    use super::super::plane::Plane;
+    use super::super::shape::Shape;
+    use super::super::sphere::Sphere;
+    use super::super::transformation::*;
+    use super::super::tuple::Tuple;
+
+    #[test]
+    fn whitted_integrator_matches_color_at() {
+        let world: World = Default::default();
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = world.color_at(ray, DEFAULT_RECURSION);
+
+        let actual = Integrator::Whitted.radiance(&world, ray, &mut Rng::new(0));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn path_tracer_sees_background_on_miss() {
+        let world = World { background: Background::Color(Color::new(0.2, 0.3, 0.4)), ..World::new() };
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = Color::new(0.2, 0.3, 0.4);
+
+        let actual = PathTracing::default().radiance(&world, ray, &mut Rng::new(0));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn path_tracer_sees_emission() {
+        let mut world = World::new();
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_emission(Color::new(3., 2., 1.)).with_diffuse(0.)
+            .with_specular(0.));
+        world.objects.push(sphere);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = Color::new(3., 2., 1.);
+
+        let actual = PathTracing::default().radiance(&world, ray, &mut Rng::new(0));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn diffuse_sphere_in_uniform_white_furnace() {
+        // Every bounce off a convex object escapes, so each path carries
+        // exactly the albedo.
+        let mut world = World { background: Background::Color(WHITE), ..World::new() };
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_diffuse(0.5).with_specular(0.));
+        world.objects.push(sphere);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let mut rng = Rng::new(3);
+
+        for _ in 0..20 {
+            let actual = PathTracing::default().radiance(&world, ray, &mut rng);
+
+            assert_eq!(Color::new(0.5, 0.5, 0.5), actual);
+        }
+    }
+
+    #[test]
+    fn first_bounce_matches_whitted_direct_lighting() {
+        let world: World = Default::default();
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let ambient = Color::new(0.8, 1., 0.6) * 0.1;
+
+        let expected = world.color_at(ray, DEFAULT_RECURSION) - ambient;
+
+        let actual = PathTracing::new(1, 1).radiance(&world, ray, &mut Rng::new(0));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn path_tracer_gathers_indirect_light() {
+        // The ceiling is only lit by light bouncing off the floor.
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 1., 0.), WHITE));
+        let mut floor = Shape::Plane(Plane::new());
+        floor.set_material(Material::new().with_specular(0.));
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_material(Material::new().with_specular(0.));
+        ceiling.set_transform(translate(0., 2., 0.));
+        let mut shade = Shape::Sphere(Sphere::new());
+        shade.set_transform(translate(0., 1.5, 0.) * scale(0.3, 0.3, 0.3));
+        world.objects.push(floor);
+        world.objects.push(ceiling);
+        world.objects.push(shade);
+        let ray = Ray::new(Tuple::point(0.5, 1., 0.), Tuple::vector(0., 1., 0.));
+        let mut rng = Rng::new(5);
+
+        let whitted = world.color_at(ray, DEFAULT_RECURSION);
+        let mut sum = BLACK;
+        for _ in 0..200 {
+            sum += PathTracing::default().radiance(&world, ray, &mut rng);
+        }
+
+        assert_eq!(Color::new(0.1, 0.1, 0.1), whitted);
+        assert!(sum.red / 200. > 0.01);
+    }
+
+    #[test]
+    fn promoted_emitters_light_as_much_as_unpromoted_ones() {
+        let world_with_lamp = |promoted: bool| {
+            let mut world = World::new();
+            let mut lamp = Shape::Sphere(Sphere::new());
+            lamp.set_material(Material::new().with_emission(WHITE));
+            lamp.set_transform(translate(0., 2., 0.));
+            world.objects.push(Shape::Plane(Plane::new()));
+            world.objects.push(lamp);
+            if promoted {
+                world.promote_emissive_shapes(16);
+            }
+
+            world
+        };
+        let samples = 10000;
+
+        // The lamp covers a cap of the sky a quarter of π in cosine weighted
+        // solid angle, reflected by the floor with an albedo of 0.9.
+        let expected = 0.9 * 0.25;
+
+        for promoted in [false, true].iter() {
+            let world = world_with_lamp(*promoted);
+            let mut rng = Rng::new(7);
+            let mut sum = BLACK;
+            for _ in 0..samples {
+                // Lights are sampled the same way at the same point, so spread
+                // the rays over a small patch of floor under the lamp.
+                let origin = Tuple::point(0.1 * rng.next_f64() - 0.05, 0.5, 0.1 * rng.next_f64() - 0.05);
+                sum += PathTracing::new(2, 2).radiance(&world, Ray::new(origin, Tuple::vector(0., -1., 0.)), &mut rng);
+            }
+
+            let actual = sum.red / samples as f64;
+
+            assert!((expected - actual).abs() < 0.05 * expected);
+        }
+    }
+
+    #[test]
+    fn emitters_seen_through_glass_are_only_counted_once() {
+        let mut world = World::new();
+        let mut pane = Shape::Plane(Plane::new());
+        pane.set_material(Material::new().with_diffuse(0.).with_transparency(1.).with_refractive_index(1.));
+        pane.set_transform(translate(0., 1., 0.));
+        let mut lamp = Shape::Sphere(Sphere::new());
+        lamp.set_material(Material::new().with_emission(WHITE));
+        lamp.set_transform(translate(0., 3., 0.));
+        world.objects.push(Shape::Plane(Plane::new()));
+        world.objects.push(pane);
+        world.objects.push(lamp);
+        world.promote_emissive_shapes(16);
+        let samples = 10000;
+
+        // Shadow rays already see the lamp through the pane, so light that
+        // reaches the floor by refraction must not be added again.
+        let expected = 0.9 / 9.;
+
+        let mut rng = Rng::new(7);
+        let mut sum = BLACK;
+        for _ in 0..samples {
+            let origin = Tuple::point(0.1 * rng.next_f64() - 0.05, 0.5, 0.1 * rng.next_f64() - 0.05);
+            sum += PathTracing::new(3, 3).radiance(&world, Ray::new(origin, Tuple::vector(0., -1., 0.)), &mut rng);
+        }
+
+        let actual = sum.red / samples as f64;
+
+        assert!((expected - actual).abs() < 0.05 * expected);
+    }
+
+    #[test]
+    fn bidirectional_sees_background_and_emission() {
+        let mut world = World { background: Background::Color(Color::new(0.2, 0.3, 0.4)), ..World::new() };
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_emission(Color::new(3., 2., 1.)).with_diffuse(0.));
+        world.objects.push(sphere);
+        let hit = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let miss = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 1., 0.));
+        let integrator = BidirectionalPathTracing::default();
+
+        assert_eq!(Color::new(3., 2., 1.), integrator.radiance(&world, hit, &mut Rng::new(0)));
+        assert_eq!(Color::new(0.2, 0.3, 0.4), integrator.radiance(&world, miss, &mut Rng::new(0)));
+    }
+
+    #[test]
+    fn bidirectional_diffuse_sphere_in_uniform_white_furnace() {
+        let mut world = World { background: Background::Color(WHITE), ..World::new() };
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_diffuse(0.5));
+        world.objects.push(sphere);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let mut rng = Rng::new(3);
+
+        for _ in 0..20 {
+            let actual = BidirectionalPathTracing::default().radiance(&world, ray, &mut rng);
+
+            assert_eq!(Color::new(0.5, 0.5, 0.5), actual);
+        }
+    }
+
+    #[test]
+    fn bidirectional_direct_lighting_matches_diffuse_shading() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(-10., 10., -10.), WHITE));
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_color(Color::new(0.8, 1., 0.6)).with_ambient(0.)
+            .with_specular(0.));
+        world.objects.push(sphere);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = world.color_at(ray, DEFAULT_RECURSION);
+
+        let actual = BidirectionalPathTracing::default().radiance(&world, ray, &mut Rng::new(0));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn bidirectional_agrees_with_path_tracer() {
+        // Without mirrors or glass for light subpaths to bounce off, both
+        // integrators find the same light.
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 1., 0.), WHITE));
+        let mut floor = Shape::Plane(Plane::new());
+        floor.set_material(Material::new().with_diffuse(0.5).with_specular(0.));
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_material(Material::new().with_diffuse(0.5).with_specular(0.));
+        ceiling.set_transform(translate(0., 2., 0.));
+        let mut shade = Shape::Sphere(Sphere::new());
+        shade.set_transform(translate(0., 1.5, 0.) * scale(0.3, 0.3, 0.3));
+        world.objects.push(floor);
+        world.objects.push(ceiling);
+        world.objects.push(shade);
+        let ray = Ray::new(Tuple::point(0.5, 1., 0.), Tuple::vector(0., 1., 0.));
+        let path_tracer = PathTracing::new(16, 3);
+        let bidirectional = BidirectionalPathTracing::new(16, 3);
+        let mut rng = Rng::new(7);
+
+        let count = 4000;
+        let mut expected = 0.;
+        let mut actual = 0.;
+        for _ in 0..count {
+            expected += path_tracer.radiance(&world, ray, &mut rng).red;
+            actual += bidirectional.radiance(&world, ray, &mut rng).red;
+        }
+
+        assert!(((actual - expected) / expected).abs() < 0.05);
+    }
+}
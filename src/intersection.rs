@@ -1,8 +1,10 @@
 use super::computations::Computations;
 use super::EPSILON;
+use super::material::beer_lambert;
 use super::near_eq;
 use super::ray::Ray;
 use super::shape::{Shape, CommonShape};
+use super::WHITE;
 
 #[derive(Debug, Clone)]
 pub struct Intersection {
@@ -53,20 +55,31 @@ impl Intersection {
 
         let mut n1 = 0.;
         let mut n2 = 0.;
+        let mut attenuation = WHITE;
         let mut containers: Vec<Shape> = Vec::new();
+        let mut entries: Vec<f64> = Vec::new();
         for intersection in intersections {
             if intersection == *self {
                 n1 = if containers.is_empty() {
                     1.
                 } else {
-                    containers.last().unwrap().get_material().refractive_index
+                    containers.last().unwrap().get_material().refractive_index_at(ray.wavelength)
                 };
+
+                // The ray travelled through the containing object since it
+                // last crossed its surface.
+                if let Some(container) = containers.last() {
+                    let distance = (self.t - entries.last().unwrap().max(0.)) * ray.direction.magnitude();
+                    attenuation = beer_lambert(container.get_material().absorption, distance);
+                }
             }
 
             if containers.contains(&intersection.object) {
                 let index = &containers.iter().position(|con| *con == intersection.object).unwrap();
                 containers.remove(*index);
+                entries.remove(*index);
             } else {
+                entries.push(intersection.t);
                 containers.push(intersection.clone().object);
             }
 
@@ -74,7 +87,7 @@ impl Intersection {
                 n2 = if containers.is_empty() {
                     1.
                 } else {
-                    containers.last().unwrap().get_material().refractive_index
+                    containers.last().unwrap().get_material().refractive_index_at(ray.wavelength)
                 }
             }
         }
@@ -91,6 +104,8 @@ impl Intersection {
             n1,
             n2,
             under_point,
+            attenuation,
+            wavelength: ray.wavelength,
         }
     }
 
@@ -132,6 +147,7 @@ macro_rules! intersections {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::color::Color;
     use super::super::computations::Computations;
     use super::super::EPSILON;
     use super::super::material::Material;
@@ -140,6 +156,7 @@ mod tests {
     use super::super::plane::Plane;
     use super::super::ray::Ray;
     use super::super::shape::Shape;
+    use super::super::spectrum::Dispersion;
     use super::super::sphere::Sphere;
     use super::super::transformation::*;
     use super::super::triangle::Triangle;
@@ -249,6 +266,8 @@ mod tests {
             n1: 1.,
             n2: 1.,
             under_point: Tuple::point(0., 0., -1.) - Tuple::vector(0., 0., -1.) * EPSILON,
+            attenuation: WHITE,
+            wavelength: None,
         };
 
         let actual = intersection.prepare_computations(ray, vec![intersection.clone()]);
@@ -285,6 +304,8 @@ mod tests {
             n1: 1.,
             n2: 1.,
             under_point: Tuple::point(0., 0., 1.) - (Tuple::vector(0., 0., -1.) * EPSILON),
+            attenuation: WHITE,
+            wavelength: None,
         };
 
         let actual = intersection.prepare_computations(ray, vec![intersection.clone()]);
@@ -352,6 +373,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn n1_and_n2_follow_wavelength_of_ray() {
+        let mut shape = Shape::Sphere(Sphere::glass_sphere());
+        shape.set_material(Material::new().with_refractive_index(1.5)
+            .with_dispersion(Dispersion::Cauchy { a: 1.5, b: 0.01 }));
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)).with_wavelength(Some(500.));
+        let intersections = intersections!(Intersection::new(4., shape.clone()), Intersection::new(6., shape));
+
+        let expected = (1., 1.54);
+
+        let actual = intersections[0].prepare_computations(ray, intersections.clone());
+
+        assert!(near_eq(expected.0, actual.n1));
+        assert!(near_eq(expected.1, actual.n2));
+    }
+
     #[test]
     fn under_point_is_offset_below_surface() {
         let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
@@ -409,6 +446,37 @@ mod tests {
         assert!(near_eq(expected, actual)); 
     }
 
+    #[test]
+    fn light_is_absorbed_over_distance_travelled_inside() {
+        let mut shape = Shape::Sphere(Sphere::glass_sphere());
+        shape.set_material(Material::new().with_absorption(Color::new(0.5, 0.1, 0.)));
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let intersections = intersections!(Intersection::new(4., shape.clone()), Intersection::new(6., shape));
+
+        let expected_entry = WHITE;
+        let expected_exit = Color::new((-1_f64).exp(), (-0.2_f64).exp(), 1.);
+
+        let actual_entry = intersections[0].prepare_computations(ray, intersections.clone()).attenuation;
+        let actual_exit = intersections[1].prepare_computations(ray, intersections.clone()).attenuation;
+
+        assert_eq!(expected_entry, actual_entry);
+        assert_eq!(expected_exit, actual_exit);
+    }
+
+    #[test]
+    fn absorption_starts_at_ray_origin_inside_object() {
+        let mut shape = Shape::Sphere(Sphere::glass_sphere());
+        shape.set_material(Material::new().with_absorption(Color::new(0.5, 0.5, 0.5)));
+        let ray = Ray::new(ORIGIN, Tuple::vector(0., 0., 1.));
+        let intersections = intersections!(Intersection::new(-1., shape.clone()), Intersection::new(1., shape));
+
+        let expected = Color::new((-0.5_f64).exp(), (-0.5_f64).exp(), (-0.5_f64).exp());
+
+        let actual = intersections[1].prepare_computations(ray, intersections.clone()).attenuation;
+
+        assert_eq!(expected, actual);
+    }
+
     #[test]
     fn intersection_can_encapsulate_u_and_v() {
         let triangle = Shape::Triangle(Triangle::new(Tuple::point(0., 1., 0.), 
@@ -1,3 +1,5 @@
+pub mod ambient_occlusion;
+pub mod background;
 pub mod bound;
 pub mod camera;
 pub mod canvas;
@@ -8,17 +10,26 @@ pub mod csg;
 pub mod cube;
 pub mod cylinder;
 pub mod group;
+pub mod hdr;
+pub mod integrator;
 pub mod intersection;
 pub mod light;
 pub mod material;
 pub mod matrix;
+pub mod medium;
 pub mod obj_file;
 pub mod pattern;
+pub mod photon_map;
 pub mod plane;
+pub mod png;
+pub mod random;
 pub mod ray;
+pub mod sampling;
 pub mod shape;
 pub mod smooth_triangle;
+pub mod spectrum;
 pub mod sphere;
+pub mod tone_mapping;
 pub mod transformation;
 pub mod triangle;
 pub mod tuple;
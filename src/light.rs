@@ -1,28 +1,589 @@
 use super::color::Color;
+use super::random::Rng;
+use super::sampling::{concentric_disk, orthonormal_basis};
+use super::shape::{CommonShape, Shape};
 use super::tuple::Tuple;
+use std::f64::consts::PI;
 
-#[derive(Debug, Copy, Clone)]
-pub struct Light {
+#[derive(Debug, Clone, PartialEq)]
+pub enum Light {
+    Point (PointLight),
+    Area (AreaLight),
+    Spot (SpotLight),
+    Directional (DirectionalLight),
+    Object (ObjectLight),
+}
+
+/// Where light arrives from: a point in the scene, or the direction towards
+/// a light infinitely far away, such as the sun.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightPosition {
+    Point (Tuple),
+    Infinite (Tuple),
+}
+
+pub trait LightTrait {
+    fn get_position(&self) -> LightPosition;
+    fn get_intensity(&self) -> Color;
+    fn set_intensity(&mut self, intensity: Color);
+    fn illumination_at(&self, point: Tuple) -> Color;
+    fn get_samples(&self) -> usize;
+    fn sample_points(&self, point: Tuple) -> Vec<LightPosition>;
+    fn get_linking(&self) -> &LightLinking;
+    fn set_linking(&mut self, linking: LightLinking);
+}
+
+impl Light {
+    pub fn point_light(position: Tuple, intensity: Color) -> Self {
+        Light::Point(PointLight::new(position, intensity))
+    }
+
+    /// The ID of the shape that emits this light, which must not block it.
+    pub fn emitter_id(&self) -> Option<i32> {
+        match self {
+            Light::Object(object) => Some(object.shape.get_id()),
+            _ => None,
+        }
+    }
+
+    pub fn with_include(mut self, include: Vec<i32>) -> Self {
+        let mut linking = self.get_linking().clone();
+        linking.include = include;
+        self.set_linking(linking);
+
+        self
+    }
+
+    pub fn with_exclude(mut self, exclude: Vec<i32>) -> Self {
+        let mut linking = self.get_linking().clone();
+        linking.exclude = exclude;
+        self.set_linking(linking);
+
+        self
+    }
+
+    pub fn with_casts_shadows(mut self, casts_shadows: bool) -> Self {
+        let mut linking = self.get_linking().clone();
+        linking.casts_shadows = casts_shadows;
+        self.set_linking(linking);
+
+        self
+    }
+}
+
+impl LightTrait for Light {
+    fn get_position(&self) -> LightPosition {
+        match self {
+            Light::Point(point) => LightPosition::Point(point.position),
+            Light::Area(area) => LightPosition::Point(area.center()),
+            Light::Spot(spot) => LightPosition::Point(spot.position),
+            Light::Directional(directional) => LightPosition::Infinite(-directional.direction),
+            Light::Object(object) => LightPosition::Point(object.center()),
+        }
+    }
+
+    fn get_intensity(&self) -> Color {
+        match self {
+            Light::Point(point) => point.intensity,
+            Light::Area(area) => area.intensity,
+            Light::Spot(spot) => spot.intensity,
+            Light::Directional(directional) => directional.intensity,
+            Light::Object(object) => object.intensity,
+        }
+    }
+
+    fn set_intensity(&mut self, intensity: Color) {
+        match self {
+            Light::Point(point) => point.intensity = intensity,
+            Light::Area(area) => area.intensity = intensity,
+            Light::Spot(spot) => spot.intensity = intensity,
+            Light::Directional(directional) => directional.intensity = intensity,
+            Light::Object(object) => object.intensity = intensity,
+        }
+    }
+
+    /// The color of the light arriving at `point`, ignoring shadows.
+    fn illumination_at(&self, point: Tuple) -> Color {
+        match self {
+            Light::Point(light) => light.intensity * light.attenuation.factor(point, light.position, light.cutoff),
+            Light::Spot(spot) => {
+                spot.intensity * spot.cone_falloff(point) * spot.attenuation.factor(point, spot.position, spot.cutoff)
+            },
+            _ => self.get_intensity(),
+        }
+    }
+
+    fn get_samples(&self) -> usize {
+        match self {
+            Light::Point(_) | Light::Spot(_) | Light::Directional(_) => 1,
+            Light::Area(area) => area.u_steps * area.v_steps,
+            Light::Object(object) => object.samples,
+        }
+    }
+
+    /// The positions on the light that are used to shade `point`. The same
+    /// point always receives the same positions.
+    fn sample_points(&self, point: Tuple) -> Vec<LightPosition> {
+        match self {
+            Light::Area(area) => area.sample_points(point).into_iter().map(LightPosition::Point).collect(),
+            Light::Object(object) => object.sample_points(point).into_iter().map(LightPosition::Point).collect(),
+            _ => vec![self.get_position()],
+        }
+    }
+
+    fn get_linking(&self) -> &LightLinking {
+        match self {
+            Light::Point(point) => &point.linking,
+            Light::Area(area) => &area.linking,
+            Light::Spot(spot) => &spot.linking,
+            Light::Directional(directional) => &directional.linking,
+            Light::Object(object) => &object.linking,
+        }
+    }
+
+    fn set_linking(&mut self, linking: LightLinking) {
+        match self {
+            Light::Point(point) => point.linking = linking,
+            Light::Area(area) => area.linking = linking,
+            Light::Spot(spot) => spot.linking = linking,
+            Light::Directional(directional) => directional.linking = linking,
+            Light::Object(object) => object.linking = linking,
+        }
+    }
+}
+
+/// Which objects a light affects and whether it casts shadows. An empty
+/// `include` list means every object that is not excluded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightLinking {
+    pub include: Vec<i32>,
+    pub exclude: Vec<i32>,
+    pub casts_shadows: bool,
+}
+
+impl Default for LightLinking {
+    fn default() -> Self {
+        Self { include: vec![], exclude: vec![], casts_shadows: true }
+    }
+}
+
+impl LightLinking {
+    /// Whether the light affects an object, given the IDs of the object and
+    /// of every group containing it.
+    pub fn illuminates(&self, ids: &[i32]) -> bool {
+        let included = self.include.is_empty() || ids.iter().any(|id| self.include.contains(id));
+        let excluded = ids.iter().any(|id| self.exclude.contains(id));
+
+        included && !excluded
+    }
+}
+
+/// The normalized direction from `point` towards a light sample and the
+/// distance to it.
+pub fn direction_to(sample: LightPosition, point: Tuple) -> (Tuple, f64) {
+    match sample {
+        LightPosition::Point(position) => {
+            let vector = position - point;
+
+            (vector.normalize(), vector.magnitude())
+        },
+        LightPosition::Infinite(direction) => (direction.normalize(), f64::INFINITY),
+    }
+}
+
+/// How a light's strength falls off with distance.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Attenuation {
+    None,
+    InverseSquare,
+    Polynomial { constant: f64, linear: f64, quadratic: f64 },
+}
+
+impl Attenuation {
+    /// The fraction of the light left after travelling from `light_position`
+    /// to `point`. Nothing reaches points beyond the cutoff radius.
+    pub fn factor(&self, point: Tuple, light_position: Tuple, cutoff: Option<f64>) -> f64 {
+        let distance = (point - light_position).magnitude();
+        if let Some(radius) = cutoff {
+            if distance > radius {
+                return 0.;
+            }
+        }
+
+        let denominator = match self {
+            Attenuation::None => 1.,
+            Attenuation::InverseSquare => distance * distance,
+            Attenuation::Polynomial { constant, linear, quadratic } => {
+                constant + linear * distance + quadratic * distance * distance
+            },
+        };
+
+        if denominator > 0. { 1. / denominator } else { 1. }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointLight {
     pub position: Tuple,
     pub intensity: Color,
+    pub attenuation: Attenuation,
+    pub cutoff: Option<f64>,
+    pub linking: LightLinking,
 }
 
-impl PartialEq for Light {
-    fn eq(&self, other: &Self) -> bool {
-        self.position == other.position && self.intensity == other.intensity
+impl PointLight {
+    pub fn new(position: Tuple, intensity: Color) -> Self {
+        Self { position, intensity, attenuation: Attenuation::None, cutoff: None, linking: Default::default() }
+    }
+
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+
+        self
+    }
+
+    pub fn with_cutoff(mut self, cutoff: f64) -> Self {
+        self.cutoff = Some(cutoff);
+
+        self
     }
 }
 
-impl Light {
-    pub fn point_light(position: Tuple, intensity: Color) -> Self {
-        Light { position, intensity }
+/// A point light that only shines inside a cone. The light is at full
+/// strength within `inner_angle` of `direction` and fades smoothly to nothing
+/// at `outer_angle`. Both angles are measured from the axis, in radians.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotLight {
+    pub position: Tuple,
+    pub direction: Tuple,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    pub intensity: Color,
+    pub attenuation: Attenuation,
+    pub cutoff: Option<f64>,
+    pub linking: LightLinking,
+}
+
+impl SpotLight {
+    pub fn new(position: Tuple, direction: Tuple, inner_angle: f64, outer_angle: f64, intensity: Color) -> Self {
+        Self { position, direction: direction.normalize(), inner_angle, outer_angle: outer_angle.max(inner_angle),
+            intensity, attenuation: Attenuation::None, cutoff: None, linking: Default::default() }
+    }
+
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+
+        self
+    }
+
+    pub fn with_cutoff(mut self, cutoff: f64) -> Self {
+        self.cutoff = Some(cutoff);
+
+        self
+    }
+
+    pub fn cone_falloff(&self, point: Tuple) -> f64 {
+        let cos_angle = (point - self.position).normalize().dot(self.direction);
+        let cos_inner = self.inner_angle.cos();
+        let cos_outer = self.outer_angle.cos();
+
+        if cos_angle >= cos_inner {
+            1.
+        } else if cos_angle <= cos_outer {
+            0.
+        } else {
+            let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+
+            t * t * (3. - 2. * t)
+        }
+    }
+}
+
+/// A light infinitely far away, such as the sun, whose rays all travel in
+/// `direction`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectionalLight {
+    pub direction: Tuple,
+    pub intensity: Color,
+    pub linking: LightLinking,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Tuple, intensity: Color) -> Self {
+        Self { direction: direction.normalize(), intensity, linking: Default::default() }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AreaShape {
+    Rectangle { corner: Tuple, u_vector: Tuple, v_vector: Tuple },
+    Disk { center: Tuple, normal: Tuple, radius: f64 },
+    Sphere { center: Tuple, radius: f64 },
+}
+
+/// A light with a surface, sampled on a `u_steps` by `v_steps` grid of cells
+/// to produce soft shadows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AreaLight {
+    pub shape: AreaShape,
+    pub intensity: Color,
+    pub u_steps: usize,
+    pub v_steps: usize,
+    pub jitter: bool,
+    pub seed: u64,
+    pub linking: LightLinking,
+}
+
+impl AreaLight {
+    pub fn rectangle(corner: Tuple, full_u_vector: Tuple, u_steps: usize, full_v_vector: Tuple,
+        v_steps: usize, intensity: Color) -> Self {
+        let shape = AreaShape::Rectangle { corner, u_vector: full_u_vector, v_vector: full_v_vector };
+
+        Self::from_shape(shape, u_steps.max(1), v_steps.max(1), intensity)
+    }
+
+    pub fn disk(center: Tuple, normal: Tuple, radius: f64, samples: usize, intensity: Color) -> Self {
+        let shape = AreaShape::Disk { center, normal: normal.normalize(), radius };
+        let steps = Self::steps_for(samples);
+
+        Self::from_shape(shape, steps, steps, intensity)
+    }
+
+    pub fn sphere(center: Tuple, radius: f64, samples: usize, intensity: Color) -> Self {
+        let shape = AreaShape::Sphere { center, radius };
+        let steps = Self::steps_for(samples);
+
+        Self::from_shape(shape, steps, steps, intensity)
+    }
+
+    fn from_shape(shape: AreaShape, u_steps: usize, v_steps: usize, intensity: Color) -> Self {
+        Self { shape, intensity, u_steps, v_steps, jitter: true, seed: 0, linking: Default::default() }
+    }
+
+    fn steps_for(samples: usize) -> usize {
+        (samples as f64).sqrt().ceil().max(1.) as usize
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+
+        self
+    }
+
+    pub fn center(&self) -> Tuple {
+        match self.shape {
+            AreaShape::Rectangle { corner, u_vector, v_vector } => corner + u_vector * 0.5 + v_vector * 0.5,
+            AreaShape::Disk { center, .. } => center,
+            AreaShape::Sphere { center, .. } => center,
+        }
+    }
+
+    /// Maps a position in the unit square onto the light's surface.
+    pub fn point_on_light(&self, u: f64, v: f64, target: Tuple) -> Tuple {
+        match self.shape {
+            AreaShape::Rectangle { corner, u_vector, v_vector } => corner + u_vector * u + v_vector * v,
+            AreaShape::Disk { center, normal, radius } => {
+                let (tangent, bitangent) = orthonormal_basis(normal);
+                let (x, y) = concentric_disk(u, v);
+
+                center + tangent * (x * radius) + bitangent * (y * radius)
+            },
+            AreaShape::Sphere { center, radius } => {
+                let z = 1. - 2. * u;
+                let r = (1. - z * z).max(0.).sqrt();
+                let phi = 2. * PI * v;
+                let mut offset = Tuple::vector(r * phi.cos(), r * phi.sin(), z);
+
+                // Only the half of the sphere facing the target can light it.
+                if offset.dot(target - center) < 0. {
+                    offset = -offset;
+                }
+
+                center + offset * radius
+            },
+        }
+    }
+
+    pub fn sample_points(&self, point: Tuple) -> Vec<Tuple> {
+        let mut rng = Rng::from_values(&[self.seed, point.x.to_bits(), point.y.to_bits(), point.z.to_bits()]);
+        let u_cell = 1. / self.u_steps as f64;
+        let v_cell = 1. / self.v_steps as f64;
+
+        let mut points = Vec::with_capacity(self.u_steps * self.v_steps);
+        for v in 0..self.v_steps {
+            for u in 0..self.u_steps {
+                let (u_offset, v_offset) = if self.jitter {
+                    (rng.next_f64(), rng.next_f64())
+                } else {
+                    (0.5, 0.5)
+                };
+                let u = (u as f64 + u_offset) * u_cell;
+                let v = (v as f64 + v_offset) * v_cell;
+
+                points.push(self.point_on_light(u, v, point));
+            }
+        }
+
+        points
+    }
+}
+
+/// An emissive shape used as a light source. Spheres and triangles, smooth or
+/// not, can be sampled. The shape does not light itself.
+///
+/// The shape is sampled on a square grid of cells, so the number of samples
+/// is rounded up to the next perfect square.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectLight {
+    pub shape: Box<Shape>,
+    pub intensity: Color,
+    pub samples: usize,
+    pub seed: u64,
+    pub linking: LightLinking,
+}
+
+impl ObjectLight {
+    /// Returns None for shapes that cannot be sampled.
+    pub fn new(shape: Shape, samples: usize) -> Option<Self> {
+        match shape {
+            Shape::Sphere(_) | Shape::Triangle(_) | Shape::SmoothTriangle(_) => (),
+            _ => return None,
+        }
+
+        let intensity = shape.get_material().emission;
+        let linking = LightLinking { exclude: vec![shape.get_id()], ..Default::default() };
+        let side = Self::side_for(samples);
+
+        Some(Self { shape: Box::new(shape), intensity, samples: side * side, seed: 0, linking })
+    }
+
+    fn side_for(samples: usize) -> usize {
+        (samples as f64).sqrt().ceil().max(1.) as usize
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+
+        self
+    }
+
+    pub fn center(&self) -> Tuple {
+        match *self.shape {
+            Shape::Triangle(_) | Shape::SmoothTriangle(_) => {
+                let (point1, point2, point3) = self.shape.get_points();
+                let centroid = (point1 + point2 + point3) * (1. / 3.);
+
+                self.shape.object_to_world(Tuple::point(centroid.x, centroid.y, centroid.z))
+            },
+            _ => self.shape.object_to_world(Tuple::point(0., 0., 0.)),
+        }
+    }
+
+    /// Maps a position in the unit square onto the surface of the shape, in
+    /// world space.
+    pub fn point_on_light(&self, u: f64, v: f64, target: Tuple) -> Tuple {
+        match *self.shape {
+            Shape::Triangle(_) | Shape::SmoothTriangle(_) => {
+                let (point1, point2, point3) = self.shape.get_points();
+                let (u, v) = if u + v > 1. { (1. - u, 1. - v) } else { (u, v) };
+
+                self.shape.object_to_world(point1 + (point2 - point1) * u + (point3 - point1) * v)
+            },
+            _ => {
+                let z = 1. - 2. * u;
+                let r = (1. - z * z).max(0.).sqrt();
+                let phi = 2. * PI * v;
+                let offset = Tuple::point(r * phi.cos(), r * phi.sin(), z);
+
+                // Only the half of the sphere facing the target can light it.
+                let center = self.center();
+                let sample = self.shape.object_to_world(offset);
+                if (sample - center).dot(target - center) < 0. {
+                    self.shape.object_to_world(Tuple::point(-offset.x, -offset.y, -offset.z))
+                } else {
+                    sample
+                }
+            },
+        }
+    }
+
+    /// The solid angle a sample from `sample_points` stands for, as seen from
+    /// `point`. Light from the shape weighted by it and averaged over the
+    /// samples estimates the light arriving from the whole shape.
+    pub fn solid_angle_at(&self, sample: Tuple, point: Tuple) -> f64 {
+        let (normal, area) = self.surface_at(sample);
+        let offset = point - sample;
+        let distance_squared = offset.dot(offset);
+        let cosine = normal.dot(offset) / distance_squared.sqrt();
+
+        // Triangles glow on both sides, while only the outside of a sphere
+        // can be seen.
+        let cosine = match *self.shape {
+            Shape::Triangle(_) | Shape::SmoothTriangle(_) => cosine.abs(),
+            _ => cosine.max(0.),
+        };
+
+        cosine * area / distance_squared
+    }
+
+    // The world space normal at a sample and the area of the surface it
+    // stands for, which is the inverse of the density of picking it.
+    fn surface_at(&self, sample: Tuple) -> (Tuple, f64) {
+        match *self.shape {
+            Shape::Triangle(_) | Shape::SmoothTriangle(_) => {
+                let (point1, point2, point3) = self.shape.get_points();
+                let corner = self.shape.object_to_world(point1);
+                let cross = (self.shape.object_to_world(point2) - corner)
+                    .cross(self.shape.object_to_world(point3) - corner);
+
+                (cross.normalize(), cross.magnitude() / 2.)
+            },
+            _ => {
+                // Samples are spread evenly over the half of the unit sphere
+                // facing the target, so measure how the transform stretches
+                // the surface around the sample.
+                let object_point = self.shape.world_to_object(sample);
+                let (tangent, bitangent) = orthonormal_basis(Tuple::vector(object_point.x, object_point.y,
+                    object_point.z).normalize());
+                let cross = (self.shape.object_to_world(object_point + tangent) - sample)
+                    .cross(self.shape.object_to_world(object_point + bitangent) - sample);
+                let normal = if cross.dot(sample - self.center()) < 0. { -cross } else { cross };
+
+                (normal.normalize(), 2. * PI * cross.magnitude())
+            },
+        }
+    }
+
+    pub fn sample_points(&self, point: Tuple) -> Vec<Tuple> {
+        let mut rng = Rng::from_values(&[self.seed, point.x.to_bits(), point.y.to_bits(), point.z.to_bits()]);
+        let side = Self::side_for(self.samples);
+        let cell = 1. / side as f64;
+
+        (0..side * side).map(|i| {
+            let u = ((i % side) as f64 + rng.next_f64()) * cell;
+            let v = ((i / side) as f64 + rng.next_f64()) * cell;
+
+            self.point_on_light(u, v, point)
+        }).collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::cube::Cube;
+    use super::super::material::Material;
+    use super::super::near_eq;
     use super::super::ORIGIN;
+    use super::super::sphere::Sphere;
+    use super::super::transformation::*;
+    use super::super::triangle::Triangle;
     use super::super::WHITE;
 
     #[test]
@@ -30,12 +591,373 @@ mod tests {
         let intensity = WHITE;
         let position = ORIGIN;
 
-        let expected_position = position;
+        let expected_position = LightPosition::Point(position);
         let expected_intensity = intensity;
 
         let actual = Light::point_light(position, intensity);
 
-        assert_eq!(expected_position, actual.position);
-        assert_eq!(expected_intensity, actual.intensity);
+        assert_eq!(expected_position, actual.get_position());
+        assert_eq!(expected_intensity, actual.get_intensity());
+    }
+
+    #[test]
+    fn creating_area_light() {
+        let corner = ORIGIN;
+        let v1 = Tuple::vector(2., 0., 0.);
+        let v2 = Tuple::vector(0., 0., 1.);
+
+        let expected_position = LightPosition::Point(Tuple::point(1., 0., 0.5));
+        let expected_samples = 8;
+
+        let actual = Light::Area(AreaLight::rectangle(corner, v1, 4, v2, 2, WHITE));
+
+        assert_eq!(expected_position, actual.get_position());
+        assert_eq!(expected_samples, actual.get_samples());
+        assert_eq!(WHITE, actual.get_intensity());
+    }
+
+    #[test]
+    fn finding_single_point_on_area_light() {
+        let corner = ORIGIN;
+        let v1 = Tuple::vector(2., 0., 0.);
+        let v2 = Tuple::vector(0., 0., 1.);
+        let light = AreaLight::rectangle(corner, v1, 4, v2, 2, WHITE);
+
+        let expected = Tuple::point(0.75, 0., 0.75);
+
+        let actual = light.point_on_light(0.375, 0.75, ORIGIN);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn unjittered_area_light_samples_cell_centers() {
+        let corner = ORIGIN;
+        let v1 = Tuple::vector(2., 0., 0.);
+        let v2 = Tuple::vector(0., 0., 1.);
+        let light = AreaLight::rectangle(corner, v1, 4, v2, 2, WHITE).with_jitter(false);
+
+        let expected = vec![
+            Tuple::point(0.25, 0., 0.25), Tuple::point(0.75, 0., 0.25),
+            Tuple::point(1.25, 0., 0.25), Tuple::point(1.75, 0., 0.25),
+            Tuple::point(0.25, 0., 0.75), Tuple::point(0.75, 0., 0.75),
+            Tuple::point(1.25, 0., 0.75), Tuple::point(1.75, 0., 0.75),
+        ];
+
+        let actual = light.sample_points(Tuple::point(0., 5., 0.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn jittered_samples_stay_in_their_cells() {
+        let corner = ORIGIN;
+        let v1 = Tuple::vector(2., 0., 0.);
+        let v2 = Tuple::vector(0., 0., 1.);
+        let light = AreaLight::rectangle(corner, v1, 4, v2, 2, WHITE);
+
+        let actual = light.sample_points(Tuple::point(0., 5., 0.));
+
+        for (i, sample) in actual.iter().enumerate() {
+            let u = (i % 4) as f64 * 0.5;
+            let v = (i / 4) as f64 * 0.5;
+
+            assert!((u..u + 0.5).contains(&sample.x));
+            assert!((v..v + 0.5).contains(&sample.z));
+        }
+    }
+
+    #[test]
+    fn jittered_samples_depend_on_seed_and_point() {
+        let light = AreaLight::rectangle(ORIGIN, Tuple::vector(1., 0., 0.), 2,
+            Tuple::vector(0., 1., 0.), 2, WHITE);
+        let point = Tuple::point(0., 0., -5.);
+
+        let expected = light.sample_points(point);
+
+        let actual_same = light.sample_points(point);
+        let actual_moved = light.sample_points(Tuple::point(0., 0., -4.));
+        let actual_seeded = light.with_seed(3).sample_points(point);
+
+        assert_eq!(expected, actual_same);
+        assert_ne!(expected, actual_seeded);
+        assert_ne!(expected, actual_moved);
+    }
+
+    #[test]
+    fn disk_light_samples_lie_on_disk() {
+        let center = Tuple::point(0., 5., 0.);
+        let light = AreaLight::disk(center, Tuple::vector(0., -1., 0.), 2., 16, WHITE);
+
+        let actual = light.sample_points(ORIGIN);
+
+        assert_eq!(16, actual.len());
+        for sample in actual {
+            assert!(near_eq(5., sample.y));
+            assert!((sample - center).magnitude() <= 2. + 0.00001);
+        }
+    }
+
+    #[test]
+    fn sphere_light_samples_face_the_point() {
+        let center = Tuple::point(0., 5., 0.);
+        let light = AreaLight::sphere(center, 1., 10, WHITE);
+
+        let actual = light.sample_points(ORIGIN);
+
+        assert_eq!(16, actual.len());
+        for sample in actual {
+            assert!(near_eq(1., (sample - center).magnitude()));
+            assert!(sample.y <= 5.);
+        }
+    }
+
+    #[test]
+    fn spot_light_is_full_strength_inside_inner_cone() {
+        let light = Light::Spot(SpotLight::new(Tuple::point(0., 10., 0.), Tuple::vector(0., -1., 0.),
+            PI / 8., PI / 4., WHITE));
+
+        let expected = WHITE;
+
+        let actual = light.illumination_at(Tuple::point(1., 0., 0.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn spot_light_is_dark_outside_outer_cone() {
+        let light = Light::Spot(SpotLight::new(Tuple::point(0., 10., 0.), Tuple::vector(0., -1., 0.),
+            PI / 8., PI / 4., WHITE));
+
+        let expected = Color::new(0., 0., 0.);
+
+        let actual_outside = light.illumination_at(Tuple::point(20., 0., 0.));
+        let actual_behind = light.illumination_at(Tuple::point(0., 20., 0.));
+
+        assert_eq!(expected, actual_outside);
+        assert_eq!(expected, actual_behind);
+    }
+
+    #[test]
+    fn spot_light_falls_off_smoothly_between_cones() {
+        let spot = SpotLight::new(ORIGIN, Tuple::vector(0., 0., 1.), 0.2, 0.6, WHITE);
+        let at_angle = |angle: f64| spot.cone_falloff(Tuple::point(angle.sin(), 0., angle.cos()));
+
+        let halfway = ((0.2_f64.cos() + 0.6_f64.cos()) / 2.).acos();
+
+        assert!(near_eq(0.5, at_angle(halfway)));
+        assert!(at_angle(0.3) > at_angle(0.4));
+        assert!(at_angle(0.4) > at_angle(0.5));
+        assert!(near_eq(1., at_angle(0.2)));
+        assert!(near_eq(0., at_angle(0.6)));
+    }
+
+    #[test]
+    fn directional_light_is_infinitely_far_away() {
+        let light = Light::Directional(DirectionalLight::new(Tuple::vector(0., -2., 0.), WHITE));
+
+        let expected = vec![LightPosition::Infinite(Tuple::vector(0., 1., 0.))];
+
+        let actual = light.sample_points(Tuple::point(5., 0., 5.));
+
+        assert_eq!(expected, actual);
+        assert_eq!(WHITE, light.illumination_at(ORIGIN));
+    }
+
+    #[test]
+    fn direction_to_point_sample_is_bounded() {
+        let expected = (Tuple::vector(0., 1., 0.), 10.);
+
+        let actual = direction_to(LightPosition::Point(Tuple::point(0., 10., 0.)), ORIGIN);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn direction_to_infinite_sample_is_unbounded() {
+        let (direction, distance) = direction_to(LightPosition::Infinite(Tuple::vector(0., 3., 4.)),
+            Tuple::point(1., 2., 3.));
+
+        assert_eq!(Tuple::vector(0., 0.6, 0.8), direction);
+        assert!(distance.is_infinite());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lights_do_not_attenuate_by_default() {
+        let light = Light::point_light(ORIGIN, WHITE);
+
+        let expected = WHITE;
+
+        let actual = light.illumination_at(Tuple::point(0., 1000., 0.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn inverse_square_attenuation() {
+        let light = Light::Point(PointLight::new(ORIGIN, Color::new(16., 16., 16.))
+            .with_attenuation(Attenuation::InverseSquare));
+
+        let expected = Color::new(1., 1., 1.);
+
+        let actual = light.illumination_at(Tuple::point(0., 0., 4.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn polynomial_attenuation() {
+        let attenuation = Attenuation::Polynomial { constant: 1., linear: 0.5, quadratic: 0.25 };
+
+        let expected = 1. / 3.;
+
+        let actual = attenuation.factor(Tuple::point(2., 0., 0.), ORIGIN, None);
+
+        assert!(near_eq(expected, actual));
+    }
+
+    #[test]
+    fn light_does_not_reach_beyond_cutoff_radius() {
+        let spot = SpotLight::new(ORIGIN, Tuple::vector(0., -1., 0.), 0.5, 0.6, WHITE).with_cutoff(5.);
+        let light = Light::Spot(spot);
+
+        let expected_inside = WHITE;
+        let expected_outside = Color::new(0., 0., 0.);
+
+        let actual_inside = light.illumination_at(Tuple::point(0., -4., 0.));
+        let actual_outside = light.illumination_at(Tuple::point(0., -6., 0.));
+
+        assert_eq!(expected_inside, actual_inside);
+        assert_eq!(expected_outside, actual_outside);
+    }
+
+    #[test]
+    fn lights_illuminate_everything_by_default() {
+        let light = Light::point_light(ORIGIN, WHITE);
+
+        assert!(light.get_linking().illuminates(&[1]));
+        assert!(light.get_linking().casts_shadows);
+    }
+
+    #[test]
+    fn light_linking_with_include_list() {
+        let light = Light::point_light(ORIGIN, WHITE).with_include(vec![1, 2]);
+
+        assert!(light.get_linking().illuminates(&[2]));
+        assert!(!light.get_linking().illuminates(&[3]));
+        assert!(light.get_linking().illuminates(&[3, 1]));
+    }
+
+    #[test]
+    fn light_linking_with_exclude_list() {
+        let light = Light::point_light(ORIGIN, WHITE).with_exclude(vec![2]).with_casts_shadows(false);
+
+        assert!(light.get_linking().illuminates(&[1]));
+        assert!(!light.get_linking().illuminates(&[2]));
+        assert!(!light.get_linking().illuminates(&[5, 2]));
+        assert!(!light.get_linking().casts_shadows);
+    }
+
+    #[test]
+    fn emissive_sphere_light_takes_its_emission() {
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_emission(Color::new(2., 2., 1.)));
+        sphere.set_transform(translate(0., 5., 0.));
+        let id = sphere.get_id();
+
+        let actual = Light::Object(ObjectLight::new(sphere, 9).unwrap());
+
+        assert_eq!(Color::new(2., 2., 1.), actual.get_intensity());
+        assert_eq!(LightPosition::Point(Tuple::point(0., 5., 0.)), actual.get_position());
+        assert_eq!(9, actual.get_samples());
+        assert_eq!(Some(id), actual.emitter_id());
+        assert!(!actual.get_linking().illuminates(&[id]));
+    }
+
+    #[test]
+    fn sphere_light_samples_lie_on_transformed_surface() {
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_transform(translate(0., 5., 0.) * scale(2., 2., 2.));
+        let light = ObjectLight::new(sphere, 16).unwrap();
+
+        let actual = light.sample_points(ORIGIN);
+
+        assert_eq!(16, actual.len());
+        for sample in actual {
+            assert!(near_eq(2., (sample - Tuple::point(0., 5., 0.)).magnitude()));
+            assert!(sample.y <= 5. + 0.00001);
+        }
+    }
+
+    #[test]
+    fn triangle_light_samples_lie_inside_triangle() {
+        let triangle = Shape::Triangle(Triangle::new(Tuple::point(0., 1., 0.), Tuple::point(-1., 0., 0.),
+            Tuple::point(1., 0., 0.)));
+        let light = ObjectLight::new(triangle, 25).unwrap();
+
+        let expected_center = Tuple::point(0., 1. / 3., 0.);
+
+        let actual = light.sample_points(Tuple::point(0., 0., -5.));
+
+        assert_eq!(expected_center, light.center());
+        for sample in actual {
+            assert!(near_eq(0., sample.z));
+            assert!(sample.y >= 0.);
+            assert!(sample.y <= 1. - sample.x.abs() + 0.00001);
+        }
+    }
+
+    #[test]
+    fn object_light_samples_are_rounded_up_to_full_grid() {
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_transform(translate(0., 5., 0.));
+        let light = Light::Object(ObjectLight::new(sphere, 5).unwrap());
+
+        // Five samples would leave the last row of a three by three grid of
+        // cells unsampled.
+        let expected = 9;
+
+        let actual = light.sample_points(ORIGIN);
+
+        assert_eq!(expected, light.get_samples());
+        assert_eq!(expected, actual.len());
+    }
+
+    #[test]
+    fn only_spheres_and_triangles_can_be_lights() {
+        let mut cube = Shape::Cube(Cube::new());
+        cube.set_material(Material::new().with_emission(WHITE));
+
+        assert_eq!(None, ObjectLight::new(cube, 4));
+    }
+
+    #[test]
+    fn object_light_samples_add_up_to_solid_angle() {
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_transform(translate(0., 4., 0.) * scale(2., 2., 2.));
+        let light = ObjectLight::new(sphere, 400).unwrap();
+        let point = ORIGIN;
+
+        // The solid angle of a sphere of radius 2 seen from a distance of 4.
+        let expected = 2. * PI * (1. - (1. - 0.25_f64).sqrt());
+
+        let samples = light.sample_points(point);
+        let actual = samples.iter().map(|sample| light.solid_angle_at(*sample, point)).sum::<f64>() /
+            samples.len() as f64;
+
+        assert!((expected - actual).abs() < 0.01 * expected);
+    }
+
+    #[test]
+    fn triangle_light_solid_angle_is_area_over_distance_squared() {
+        let triangle = Shape::Triangle(Triangle::new(Tuple::point(0., 10., 0.), Tuple::point(0.1, 10., 0.),
+            Tuple::point(0., 10., 0.1)));
+        let light = ObjectLight::new(triangle, 1).unwrap();
+
+        let expected = 0.005 / 100.;
+
+        let actual = light.solid_angle_at(Tuple::point(0., 10., 0.), ORIGIN);
+
+        assert!(near_eq(expected, actual));
+    }
+}
@@ -8,7 +8,7 @@ use ray_tracer::cylinder::Cylinder;
 use ray_tracer::Environment;
 use ray_tracer::hexagon;
 use ray_tracer::intersection::Intersection;
-use ray_tracer::light::Light;
+use ray_tracer::light::{Attenuation, DirectionalLight, Light, PointLight};
 use ray_tracer::material::Material;
 use ray_tracer::near_eq;
 use ray_tracer::obj_file::obj_to_group;
@@ -21,6 +21,7 @@ use ray_tracer::ray::Ray;
 use ray_tracer::shape::{Shape, CommonShape};
 use ray_tracer::sphere::Sphere;
 use ray_tracer::tick;
+use ray_tracer::tone_mapping::{ToneMapOperator, ToneMapper};
 use ray_tracer::transformation::*;
 use ray_tracer::tuple::Tuple;
 use ray_tracer::WHITE;
@@ -134,7 +135,7 @@ pub fn draw_circle() {
                 let eye = -ray.direction;
 
                 let material = hit.unwrap().object.get_material();
-                color = material.lighting(Shape::Sphere(shape.clone()), light, point, eye, normal, false);
+                color = material.lighting(Shape::Sphere(shape.clone()), &light, point, eye, normal, WHITE);
                 canvas.write_pixel(x as u32, y as u32, color);
             }
         }
@@ -319,8 +320,10 @@ pub fn draw_room_scene() {
     sphere.set_material(sphere_material);
     sphere.set_transform(translate(0., 1., 0.) * scale(1., 1., 1.));
     
+    let attenuation = Attenuation::Polynomial { constant: 1., linear: 0.045, quadratic: 0.0075 };
     let mut world = World::new();
-    world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+    world.lights.push(Light::Point(PointLight::new(Tuple::point(0., 10., 0.), Color::new(2., 2., 2.))
+        .with_attenuation(attenuation)));
     world.objects.push(floor);
     world.objects.push(sphere);
     world.objects.push(north_wall);
@@ -890,7 +893,7 @@ pub fn render_cover_image() {
     
     let mut world = World::new();
     world.lights.push(Light::point_light(Tuple::point(50., 100., -50.), Color::new(1., 1., 1.)));
-    world.lights.push(Light::point_light(Tuple::point(-400., 50., -10.), Color::new(0.2, 0.2, 0.2)));
+    world.lights.push(Light::Directional(DirectionalLight::new(Tuple::vector(400., -50., 10.), Color::new(0.2, 0.2, 0.2))));
 
     let mut plane = Shape::Plane(Plane::new());
     let plane_material = Material::new().with_color(WHITE).with_ambient(1.)
@@ -997,6 +1000,7 @@ pub fn render_cover_image() {
     camera.transform = view_transform(Tuple::point(-6., 6., -10.), Tuple::point(6., 0., 6.), Tuple::vector(-0.45, 1., 0.));
     
     let canvas = camera.render(world);
+    let canvas = ToneMapper::new().with_operator(ToneMapOperator::Aces).apply(&canvas);
 
     fs::write("cover_image.ppm", canvas.canvas_to_ppm()).expect("File could not be written.");
 }
\ No newline at end of file
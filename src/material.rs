@@ -1,9 +1,11 @@
 use super::BLACK;
 use super::color::Color;
-use super::light::Light;
+use super::light::{direction_to, Light, LightTrait};
+use super::medium::Medium;
 use super::near_eq;
 use super::pattern::{Pattern, PatternTrait};
 use super::shape::Shape;
+use super::spectrum::Dispersion;
 use super::tuple::Tuple;
 use super::WHITE;
 
@@ -18,6 +20,15 @@ pub struct Material {
     pub reflective: f64,
     pub transparency: f64,
     pub refractive_index: f64,
+    /// Replaces the refractive index for rays traced at a single wavelength.
+    pub dispersion: Option<Dispersion>,
+    pub emission: Color,
+    /// How strongly light is absorbed per unit distance travelled inside the
+    /// object, for each color channel.
+    pub absorption: Color,
+    /// The volume filling a closed shape. Pair it with a transparent surface
+    /// to see into it.
+    pub medium: Option<Medium>,
 }
 
 impl Default for Material {
@@ -32,6 +43,10 @@ impl Default for Material {
             reflective: 0.,
             transparency: 0.,
             refractive_index: 1.,
+            dispersion: None,
+            emission: BLACK,
+            absorption: BLACK,
+            medium: None,
         }
     }
 }
@@ -94,46 +109,92 @@ impl Material {
         self
     }
 
-    pub fn lighting(&self, object: Shape, light: Light, point: Tuple, eye_vector: Tuple, normal_vector: Tuple, in_shadow: bool) -> Color {
-        let real_color = if self.pattern.is_some() {
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Color) -> Self {
+        self.emission = emission;
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn with_medium(mut self, medium: Medium) -> Self {
+        self.medium = Some(medium);
+        self
+    }
+
+    /// The refractive index for light of the given wavelength in nanometres.
+    pub fn refractive_index_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.refractive_index(wavelength),
+            _ => self.refractive_index,
+        }
+    }
+
+    /// The surface color at `point`, taking the pattern into account.
+    pub fn color_at(&self, object: Shape, point: Tuple) -> Color {
+        if self.pattern.is_some() {
             self.pattern.clone().unwrap().pattern_at_shape(object, point)
         } else {
             self.color
-        };
+        }
+    }
 
-        let effective_color = real_color * light.intensity;
-        let light_vector = (light.position - point).normalize();
-        let ambient = effective_color * self.ambient;
+    /// Phong shading for a single light. `transmittance` is the part of the
+    /// light that reaches `point`: white when unobstructed, black when fully
+    /// shadowed and tinted when filtered through transparent objects.
+    pub fn lighting(&self, object: Shape, light: &Light, point: Tuple, eye_vector: Tuple, normal_vector: Tuple, transmittance: Color) -> Color {
+        let real_color = self.color_at(object, point);
+
+        let ambient = real_color * light.get_intensity() * self.ambient;
+        let light_intensity = light.illumination_at(point);
+        let effective_color = real_color * light_intensity;
+        if transmittance == BLACK {
+            return ambient;
+        }
 
-        let light_dot_normal = light_vector.dot(normal_vector);
-        let diffuse: Color;
-        let specular: Color;
+        let samples = light.sample_points(point);
+        let mut sum = BLACK;
+        for sample in samples.iter() {
+            let (light_vector, _) = direction_to(*sample, point);
+            let light_dot_normal = light_vector.dot(normal_vector);
+            if light_dot_normal < 0. {
+                continue;
+            }
+
+            sum += effective_color * self.diffuse * light_dot_normal;
 
-        if light_dot_normal < 0. {
-            diffuse = BLACK;
-            specular = BLACK;
-        } else {
-            diffuse = effective_color * self.diffuse * light_dot_normal;
-            
             let reflect_vector = (-light_vector).reflect(normal_vector);
             let reflect_dot_eye = reflect_vector.dot(eye_vector);
-            if near_eq(0., reflect_dot_eye) || reflect_dot_eye < 0. {
-                specular = BLACK; 
-            } else {
+            if !near_eq(0., reflect_dot_eye) && reflect_dot_eye > 0. {
                 let factor = reflect_dot_eye.powf(self.shininess);
-                specular = light.intensity * self.specular * factor;
+                sum += light_intensity * self.specular * factor;
             }
         }
 
-        if in_shadow { ambient } else { ambient + diffuse + specular }
+        ambient + sum * transmittance * (1. / samples.len() as f64)
     }
 }
 
+/// The fraction of light left after travelling `distance` through something
+/// absorbing it at the given rate per unit distance (the Beer–Lambert law).
+pub fn beer_lambert(absorption: Color, distance: f64) -> Color {
+    let channel = |coefficient: f64| if coefficient > 0. { (-coefficient * distance).exp() } else { 1. };
+
+    Color::new(channel(absorption.red), channel(absorption.green), channel(absorption.blue))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use super::super::color::Color;
-    use super::super::light::Light;
+    use super::super::light::{AreaLight, DirectionalLight, Light, SpotLight};
     use super::super::ORIGIN;
     use super::super::pattern::*;
     use super::super::sphere::Sphere;
@@ -147,6 +208,7 @@ mod tests {
         let expected_diffuse = 0.9;
         let expected_specular = 0.9;
         let expected_shininess = 200.;
+        let expected_emission = BLACK;
 
         let actual: Material = Default::default();
 
@@ -155,6 +217,7 @@ mod tests {
         assert_eq!(expected_diffuse, actual.diffuse);
         assert_eq!(expected_specular, actual.specular);
         assert_eq!(expected_shininess, actual.shininess);
+        assert_eq!(expected_emission, actual.emission);
     }
 
     #[test]
@@ -168,7 +231,7 @@ mod tests {
 
         let expected = Color::new(1.9, 1.9, 1.9);
 
-        let actual = material.lighting(sphere, light, position, eye_vector, normal_vector, false);
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
 
         assert_eq!(expected, actual);
     }
@@ -184,7 +247,7 @@ mod tests {
 
         let expected = WHITE;
 
-        let actual = material.lighting(sphere, light, position, eye_vector, normal_vector, false);
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
 
         assert_eq!(expected, actual);
     }
@@ -200,7 +263,7 @@ mod tests {
 
         let expected = Color::new(0.7364, 0.7364, 0.7364);
 
-        let actual = material.lighting(sphere, light, position, eye_vector, normal_vector, false);
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
 
         assert_eq!(expected, actual);
     }
@@ -216,7 +279,7 @@ mod tests {
 
         let expected = Color::new(1.6364, 1.6364, 1.6364);
 
-        let actual = material.lighting(sphere, light, position, eye_vector, normal_vector, false);
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
 
         assert_eq!(expected, actual);
     }
@@ -232,7 +295,7 @@ mod tests {
 
         let expected = Color::new(0.1, 0.1, 0.1);
 
-        let actual = material.lighting(sphere, light, position, eye_vector, normal_vector, false);
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
 
         assert_eq!(expected, actual);
     }
@@ -244,12 +307,12 @@ mod tests {
         let eye_vector = Tuple::vector(0., 0., -1.);
         let normal_vector = Tuple::vector(0., 0., -1.);
         let light = Light::point_light(Tuple::point(0., 0., -10.), WHITE);
-        let in_shadow = true;
+        let transmittance = BLACK;
         let sphere = Shape::Sphere(Sphere::new());
 
         let expected = Color::new(0.1, 0.1, 0.1);
 
-        let actual = material.lighting(sphere, light, position, eye_vector, normal_vector, in_shadow);
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, transmittance);
 
         assert_eq!(expected, actual);
     }
@@ -269,8 +332,8 @@ mod tests {
         let expected_color1 = WHITE;
         let expected_color2 = BLACK;
 
-        let actual_color1 = material.lighting(sphere.clone(), light, Tuple::point(0.9, 0., 0.), eye_vector, normal_vector, false);
-        let actual_color2 = material.lighting(sphere, light, Tuple::point(1.1, 0., 0.), eye_vector, normal_vector, false);
+        let actual_color1 = material.lighting(sphere.clone(), &light, Tuple::point(0.9, 0., 0.), eye_vector, normal_vector, WHITE);
+        let actual_color2 = material.lighting(sphere, &light, Tuple::point(1.1, 0., 0.), eye_vector, normal_vector, WHITE);
 
         assert_eq!(expected_color1, actual_color1);
         assert_eq!(expected_color2, actual_color2);
@@ -313,4 +376,134 @@ mod tests {
         
         assert_eq!(expected, actual);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lighting_uses_light_intensity_to_attenuate_color() {
+        let material = Material::new().with_ambient(0.1).with_diffuse(0.9).with_specular(0.)
+            .with_color(WHITE);
+        let light = Light::point_light(Tuple::point(0., 0., -10.), WHITE);
+        let point = Tuple::point(0., 0., -1.);
+        let eye_vector = Tuple::vector(0., 0., -1.);
+        let normal_vector = Tuple::vector(0., 0., -1.);
+        let sphere = Shape::Sphere(Sphere::new());
+
+        let expected = vec![WHITE, Color::new(0.55, 0.55, 0.55), Color::new(0.1, 0.1, 0.1)];
+
+        let actual = [1., 0.5, 0.].iter().map(|intensity| {
+            let transmittance = Color::new(*intensity, *intensity, *intensity);
+
+            material.lighting(sphere.clone(), &light, point, eye_vector, normal_vector, transmittance)
+        }).collect::<Vec<Color>>();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn lighting_samples_the_area_light() {
+        let corner = Tuple::point(-0.5, -0.5, -5.);
+        let v1 = Tuple::vector(1., 0., 0.);
+        let v2 = Tuple::vector(0., 1., 0.);
+        let light = Light::Area(AreaLight::rectangle(corner, v1, 2, v2, 2, WHITE).with_jitter(false));
+        let material = Material::new().with_ambient(0.1).with_diffuse(0.9).with_specular(0.)
+            .with_color(WHITE);
+        let sphere = Shape::Sphere(Sphere::new());
+        let eye = Tuple::point(0., 0., -5.);
+        let points = [Tuple::point(0., 0., -1.), Tuple::point(0., 0.7071, -0.7071)];
+
+        let expected = [Color::new(0.9965, 0.9965, 0.9965), Color::new(0.62318, 0.62318, 0.62318)];
+
+        let actual = points.iter().map(|point| {
+            let eye_vector = (eye - *point).normalize();
+            let normal_vector = Tuple::vector(point.x, point.y, point.z);
+
+            material.lighting(sphere.clone(), &light, *point, eye_vector, normal_vector, WHITE)
+        }).collect::<Vec<Color>>();
+
+        for (expected, actual) in expected.iter().zip(actual) {
+            assert!((expected.red - actual.red).abs() < 0.0001);
+            assert!((expected.green - actual.green).abs() < 0.0001);
+            assert!((expected.blue - actual.blue).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn lighting_with_spot_light_outside_cone_is_ambient_only() {
+        let material: Material = Default::default();
+        let position = ORIGIN;
+        let eye_vector = Tuple::vector(0., 0., -1.);
+        let normal_vector = Tuple::vector(0., 0., -1.);
+        let light = Light::Spot(SpotLight::new(Tuple::point(0., 0., -10.), Tuple::vector(0., 1., 0.),
+            0.2, 0.4, WHITE));
+        let sphere = Shape::Sphere(Sphere::new());
+
+        let expected = Color::new(0.1, 0.1, 0.1);
+
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn lighting_with_spot_light_inside_cone_matches_point_light() {
+        let material: Material = Default::default();
+        let position = ORIGIN;
+        let eye_vector = Tuple::vector(0., 0., -1.);
+        let normal_vector = Tuple::vector(0., 0., -1.);
+        let light = Light::Spot(SpotLight::new(Tuple::point(0., 0., -10.), Tuple::vector(0., 0., 1.),
+            0.2, 0.4, WHITE));
+        let sphere = Shape::Sphere(Sphere::new());
+
+        let expected = Color::new(1.9, 1.9, 1.9);
+
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn lighting_with_directional_light_uses_its_direction() {
+        let material: Material = Default::default();
+        let position = Tuple::point(100., 0., 0.);
+        let eye_vector = Tuple::vector(0., 0., -1.);
+        let normal_vector = Tuple::vector(0., 0., -1.);
+        let light = Light::Directional(DirectionalLight::new(Tuple::vector(0., 0., 1.), WHITE));
+        let sphere = Shape::Sphere(Sphere::new());
+
+        let expected = Color::new(1.9, 1.9, 1.9);
+
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, WHITE);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn lighting_with_filtered_light_tints_diffuse_and_specular() {
+        let material: Material = Default::default();
+        let position = ORIGIN;
+        let eye_vector = Tuple::vector(0., 0., -1.);
+        let normal_vector = Tuple::vector(0., 0., -1.);
+        let light = Light::point_light(Tuple::point(0., 0., -10.), WHITE);
+        let sphere = Shape::Sphere(Sphere::new());
+
+        let expected = Color::new(1.9, 0.1, 0.1);
+
+        let actual = material.lighting(sphere, &light, position, eye_vector, normal_vector, Color::new(1., 0., 0.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn dispersion_sets_refractive_index_per_wavelength() {
+        let material = Material::new().with_refractive_index(1.5)
+            .with_dispersion(Dispersion::Cauchy { a: 1.5, b: 0.01 });
+
+        let expected_without_wavelength = 1.5;
+        let expected_with_wavelength = 1.54;
+
+        let actual_without_wavelength = material.refractive_index_at(None);
+        let actual_with_wavelength = material.refractive_index_at(Some(500.));
+
+        assert!(near_eq(expected_without_wavelength, actual_without_wavelength));
+        assert!(near_eq(expected_with_wavelength, actual_with_wavelength));
+    }
+}
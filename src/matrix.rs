@@ -148,7 +148,10 @@ impl Matrix {
 
         drop(read_reference);
         let mut write_reference = CACHED_INVERSES.write().unwrap();
-        write_reference.push(inverse.clone());
+        // Another thread may have cached the same inverse in the meantime.
+        if !write_reference.iter().any(|ci| ci.id == self.id) {
+            write_reference.push(inverse.clone());
+        }
 
         Some(inverse)
     }
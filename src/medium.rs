@@ -0,0 +1,250 @@
+use super::BLACK;
+use super::color::Color;
+use super::light::{direction_to, LightTrait};
+use super::material::beer_lambert;
+use super::random::Rng;
+use super::ray::Ray;
+use super::WHITE;
+use super::world::World;
+use std::f64::consts::PI;
+
+// Unbounded segments are integrated until only this much light gets through.
+const NEGLIGIBLE_TRANSMITTANCE: f64 = 0.001;
+
+/// How a medium redistributes the light it scatters.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PhaseFunction {
+    Isotropic,
+    /// Forward scattering for positive asymmetry, backward for negative.
+    HenyeyGreenstein (f64),
+}
+
+impl PhaseFunction {
+    /// The density of light scattered by `cos_theta`, the cosine of the angle
+    /// between the direction the light was travelling and its new direction.
+    pub fn evaluate(&self, cos_theta: f64) -> f64 {
+        match self {
+            PhaseFunction::Isotropic => 1. / (4. * PI),
+            PhaseFunction::HenyeyGreenstein(g) => {
+                let denominator = 1. + g * g - 2. * g * cos_theta;
+
+                (1. - g * g) / (4. * PI * denominator * denominator.sqrt())
+            },
+        }
+    }
+}
+
+/// A homogeneous volume, such as fog or murky water. The absorption and
+/// scattering coefficients are per unit distance for each color channel.
+///
+/// Light scattered toward the eye is estimated from `samples` points along
+/// each ray, lit directly by the lights in the world (single scattering).
+/// Media are rendered by `World::color_at`; the other integrators only see
+/// them dim shadow rays.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    pub phase: PhaseFunction,
+    pub samples: usize,
+    pub seed: u64,
+}
+
+impl Medium {
+    pub fn new(absorption: Color, scattering: Color) -> Self {
+        Self { absorption, scattering, phase: PhaseFunction::Isotropic, samples: 8, seed: 0 }
+    }
+
+    pub fn with_phase(mut self, phase: PhaseFunction) -> Self {
+        self.phase = phase;
+
+        self
+    }
+
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+
+        self
+    }
+
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    /// The fraction of light that makes it `distance` through the medium.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        beer_lambert(self.extinction(), distance)
+    }
+
+    /// Light scattered toward the origin of the ray by the medium between it
+    /// and `distance` along it.
+    pub fn in_scattering(&self, world: &World, ray: Ray, distance: f64) -> Color {
+        if self.scattering == BLACK || self.samples == 0 {
+            return BLACK;
+        }
+
+        let speed = ray.direction.magnitude();
+        let mut length = distance * speed;
+        if !length.is_finite() {
+            let extinction = self.extinction();
+            let thinnest = [extinction.red, extinction.green, extinction.blue].iter().cloned()
+                .filter(|coefficient| *coefficient > 0.).fold(f64::INFINITY, f64::min);
+
+            length = -NEGLIGIBLE_TRANSMITTANCE.ln() / thinnest;
+        }
+        if !length.is_finite() || length <= 0. {
+            return BLACK;
+        }
+
+        let direction = ray.direction / speed;
+        let mut rng = Rng::from_values(&[self.seed, ray.origin.x.to_bits(), ray.origin.y.to_bits(),
+            ray.origin.z.to_bits(), direction.x.to_bits(), direction.y.to_bits(), direction.z.to_bits()]);
+        let step = length / self.samples as f64;
+
+        let mut sum = BLACK;
+        for i in 0..self.samples {
+            let t = (i as f64 + rng.next_f64()) * step;
+            let point = ray.origin + direction * t;
+
+            let mut lit = BLACK;
+            for light in world.lights.iter() {
+                let illumination = light.illumination_at(point);
+                if illumination == BLACK {
+                    continue;
+                }
+
+                let samples = light.sample_points(point);
+                let mut incoming = BLACK;
+                for sample in samples.iter() {
+                    let (light_vector, _) = direction_to(*sample, point);
+                    let transmittance = if light.get_linking().casts_shadows {
+                        world.transmittance_ignoring(point, *sample, light.emitter_id())
+                    } else {
+                        WHITE
+                    };
+
+                    incoming += transmittance * self.phase.evaluate(light_vector.dot(direction));
+                }
+
+                // Lights deliver π times their intensity, as in the diffuse
+                // term of `Material::lighting`.
+                lit += illumination * incoming * (PI / samples.len() as f64);
+            }
+
+            sum += lit * self.transmittance(t);
+        }
+
+        self.scattering * sum * step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::light::Light;
+    use super::super::material::Material;
+    use super::super::near_eq;
+    use super::super::shape::{CommonShape, Shape};
+    use super::super::sphere::Sphere;
+    use super::super::transformation::*;
+    use super::super::tuple::Tuple;
+
+    #[test]
+    fn transmittance_falls_off_exponentially() {
+        let medium = Medium::new(Color::new(0.1, 0.2, 0.), Color::new(0.1, 0., 0.));
+
+        let expected = Color::new((-0.4_f64).exp(), (-0.4_f64).exp(), 1.);
+
+        let actual = medium.transmittance(2.);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn henyey_greenstein_without_asymmetry_is_isotropic() {
+        let phase = PhaseFunction::HenyeyGreenstein(0.);
+
+        for cos_theta in [-1., -0.3, 0., 0.5, 1.].iter() {
+            assert!(near_eq(PhaseFunction::Isotropic.evaluate(*cos_theta), phase.evaluate(*cos_theta)));
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_integrates_to_one() {
+        for g in [-0.7, 0.3, 0.8].iter() {
+            let phase = PhaseFunction::HenyeyGreenstein(*g);
+            let steps = 10000;
+            let step = 2. / steps as f64;
+
+            let sum = (0..steps).map(|i| {
+                let cos_theta = -1. + (i as f64 + 0.5) * step;
+
+                phase.evaluate(cos_theta) * 2. * PI * step
+            }).sum::<f64>();
+
+            assert!((sum - 1.).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn forward_scattering_favours_light_ahead() {
+        let phase = PhaseFunction::HenyeyGreenstein(0.6);
+
+        assert!(phase.evaluate(1.) > phase.evaluate(-1.));
+    }
+
+    #[test]
+    fn in_scattering_from_point_light() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 1., 0.), WHITE));
+        let medium = Medium::new(Color::new(0.1, 0.1, 0.1), Color::new(0.2, 0.2, 0.2)).with_samples(64);
+        let ray = Ray::new(Tuple::point(-1., 0., 0.), Tuple::vector(1., 0., 0.));
+
+        // With an isotropic phase function each point receives 1/4 of the
+        // light's intensity, dimmed by the medium along the ray.
+        let expected = 0.2 * 0.25 * (1. - (-0.3 * 2_f64).exp()) / 0.3;
+
+        let actual = medium.in_scattering(&world, ray, 2.);
+
+        assert!((expected - actual.red).abs() < 0.01 * expected);
+    }
+
+    #[test]
+    fn hidden_lights_scatter_nothing() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 1., 0.), WHITE));
+        let mut shade = Shape::Sphere(Sphere::new());
+        shade.set_transform(translate(0., 1., 0.) * scale(0.5, 0.5, 0.5));
+        shade.set_material(Material::new());
+        world.objects.push(shade);
+        let medium = Medium::new(BLACK, Color::new(0.2, 0.2, 0.2));
+        let ray = Ray::new(Tuple::point(-1., 0., 0.), Tuple::vector(1., 0., 0.));
+
+        let expected = BLACK;
+
+        let actual = medium.in_scattering(&world, ray, 2.);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn unbounded_rays_scatter_until_medium_is_opaque() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 1., 0.), WHITE));
+        let medium = Medium::new(BLACK, Color::new(0.5, 0.5, 0.5)).with_samples(64);
+        let ray = Ray::new(Tuple::point(-1., 0., 0.), Tuple::vector(1., 0., 0.));
+
+        // Light from every point along the ray, dimmed to nothing by distance.
+        let expected = 0.25;
+
+        let actual = medium.in_scattering(&world, ray, f64::INFINITY);
+
+        assert!((expected - actual.red).abs() < 0.01);
+    }
+}
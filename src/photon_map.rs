@@ -0,0 +1,530 @@
+use super::{BLACK, WHITE};
+use super::bound::Bound;
+use super::color::Color;
+use super::computations::Computations;
+use super::intersection::Intersection;
+use super::light::{Light, LightPosition, LightTrait};
+use super::random::Rng;
+use super::ray::Ray;
+use super::sampling::{concentric_disk, orthonormal_basis, uniform_cone};
+use super::shape::{CommonShape, Shape};
+use super::tuple::Tuple;
+use super::world::World;
+use std::f64::consts::PI;
+
+// How far behind the targets photons from directional lights start.
+const DISTANT_LIGHT_OFFSET: f64 = 1000.;
+
+/// Light left on a diffuse surface after at least one specular bounce.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Photon {
+    pub position: Tuple,
+    /// The direction the photon was travelling when it landed.
+    pub direction: Tuple,
+    pub power: Color,
+}
+
+/// Settings for the caustics pass. Each light shoots `photons` photons at the
+/// reflective and transparent objects in the world, following them through
+/// at most `max_depth` bounces. Brightness is estimated from the photons
+/// within `gather_radius` of a point.
+///
+/// Objects with unbounded extents, such as planes, are not aimed at, so they
+/// only produce caustics from photons aimed at other objects and shadow rays
+/// still pass through them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhotonMapping {
+    pub photons: usize,
+    pub gather_radius: f64,
+    pub max_depth: u32,
+    pub seed: u64,
+}
+
+impl PhotonMapping {
+    pub fn new(photons: usize, gather_radius: f64) -> Self {
+        Self { photons, gather_radius, max_depth: 8, seed: 0 }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+
+        self
+    }
+
+    /// Traces photons through the world and stores where they land.
+    pub fn build(&self, world: &World) -> PhotonMap {
+        let mut targets = vec![];
+        for object in world.objects.iter() {
+            Self::collect_targets(object, &mut targets);
+        }
+        let mut photons = vec![];
+        let ids = targets.iter().map(|(id, _, _)| *id).collect::<Vec<i32>>();
+        if targets.is_empty() || self.photons == 0 {
+            return PhotonMap::new(photons, self.gather_radius).with_targets(ids);
+        }
+
+        let mut rng = Rng::new(self.seed);
+        let per_target = (self.photons / targets.len()).max(1);
+        for light in world.lights.iter() {
+            for (_, center, radius) in targets.iter() {
+                for _ in 0..per_target {
+                    let emitted = Self::emit(light, *center, *radius, per_target, &mut rng);
+                    self.trace(world, light, emitted, &mut rng, &mut photons);
+                }
+            }
+        }
+
+        PhotonMap::new(photons, self.gather_radius).with_targets(ids)
+    }
+
+    // The IDs and world space bounding spheres of the reflective and
+    // transparent objects, including those inside groups and CSG shapes.
+    fn collect_targets(object: &Shape, targets: &mut Vec<(i32, Tuple, f64)>) {
+        match object {
+            Shape::Group(_) => {
+                for child in object.get_shapes() {
+                    Self::collect_targets(child, targets);
+                }
+            },
+            Shape::CSG(csg) => {
+                Self::collect_targets(&csg.left, targets);
+                Self::collect_targets(&csg.right, targets);
+            },
+            _ => {
+                let material = object.get_material();
+                if material.reflective <= 0. && material.transparency <= 0. {
+                    return;
+                }
+
+                let bounds = object.bounds_of();
+                let mut world_bounds = Bound::bounding_box_empty();
+                for x in [bounds.minimum.x, bounds.maximum.x].iter() {
+                    for y in [bounds.minimum.y, bounds.maximum.y].iter() {
+                        for z in [bounds.minimum.z, bounds.maximum.z].iter() {
+                            world_bounds.add_point(object.object_to_world(Tuple::point(*x, *y, *z)));
+                        }
+                    }
+                }
+                let extent = world_bounds.maximum - world_bounds.minimum;
+                if !(extent.x.is_finite() && extent.y.is_finite() && extent.z.is_finite()) {
+                    return;
+                }
+
+                targets.push((object.get_id(), world_bounds.minimum + extent * 0.5, extent.magnitude() / 2.));
+            },
+        }
+    }
+
+    // A photon aimed at the bounding sphere, with the fraction of the light's
+    // intensity it carries and whether it comes from infinitely far away.
+    fn emit(light: &Light, center: Tuple, radius: f64, count: usize, rng: &mut Rng) -> (Ray, f64, bool) {
+        let samples = light.sample_points(center);
+        let position = match samples[((rng.next_f64() * samples.len() as f64) as usize).min(samples.len() - 1)] {
+            LightPosition::Point(position) => position,
+            // Light from infinitely far away arrives in parallel over the
+            // disk facing it.
+            LightPosition::Infinite(towards_light) => {
+                let direction = -towards_light.normalize();
+                let (tangent, bitangent) = orthonormal_basis(direction);
+                let (x, y) = concentric_disk(rng.next_f64(), rng.next_f64());
+                let origin = center + (tangent * x + bitangent * y) * radius -
+                    direction * (radius + DISTANT_LIGHT_OFFSET);
+
+                return (Ray::new(origin, direction), PI * radius * radius / count as f64, true);
+            },
+        };
+        let to_center = center - position;
+        let distance = to_center.magnitude();
+
+        // Aim at the cone around the bounding sphere, or everywhere if the
+        // light is inside it.
+        let (axis, cos_max) = if distance <= radius {
+            (Tuple::vector(0., 1., 0.), -1.)
+        } else {
+            (to_center / distance, (1. - (radius / distance).powi(2)).sqrt())
+        };
+        let direction = uniform_cone(axis, cos_max, rng.next_f64(), rng.next_f64());
+        let solid_angle = 2. * PI * (1. - cos_max);
+
+        (Ray::new(position, direction), solid_angle / count as f64, false)
+    }
+
+    fn trace(&self, world: &World, light: &Light, emitted: (Ray, f64, bool), rng: &mut Rng,
+        photons: &mut Vec<Photon>) {
+        let (mut ray, weight, distant) = emitted;
+        let (origin, heading) = (ray.origin, ray.direction.normalize());
+        let emitter = light.emitter_id();
+        let mut power = WHITE * weight;
+        let mut travelled = 0.;
+        let mut specular = false;
+
+        for depth in 0..self.max_depth {
+            let intersections = world.intersect_world(ray);
+            let hit = match intersections.iter().find(|i| i.t > 0. && Some(i.object.get_id()) != emitter) {
+                Some(hit) => hit.clone(),
+                None => break,
+            };
+            let computations = hit.prepare_computations(ray, intersections);
+            travelled += hit.t * ray.direction.magnitude();
+
+            if depth == 0 && light.illumination_at(computations.point) == BLACK {
+                break;
+            }
+            power = power * computations.attenuation;
+
+            let material = computations.object.get_material();
+            // Photons only land on surfaces the light is linked to.
+            let linked = light.get_linking().illuminates(&World::object_ids(&computations.object));
+            if specular && material.diffuse > 0. && linked {
+                // Photons spread out with the square of the distance they
+                // travelled, so undo that and dim them by the light's own
+                // falloff over the same distance instead.
+                let (illumination, spreading) = if distant {
+                    (light.illumination_at(computations.point), 1.)
+                } else {
+                    (light.illumination_at(origin + heading * travelled), travelled * travelled)
+                };
+
+                photons.push(Photon { position: computations.point, direction: ray.direction.normalize(),
+                    power: power * illumination * spreading });
+            }
+
+            let (reflect_weight, transmit_weight) = if material.reflective > 0. && material.transparency > 0. {
+                let reflectance = Intersection::schlick(computations.clone());
+
+                (material.reflective * reflectance, material.transparency * (1. - reflectance))
+            } else {
+                (material.reflective, material.transparency)
+            };
+            let total = reflect_weight + transmit_weight;
+            if total <= 0. {
+                break;
+            }
+
+            let survival = total.min(1.);
+            if rng.next_f64() >= survival {
+                break;
+            }
+            power = power * (total / survival);
+
+            ray = if rng.next_f64() * total < reflect_weight {
+                Ray::new(computations.over_point, computations.reflect_vector)
+            } else {
+                match World::refracted_direction(&computations) {
+                    Some(direction) => {
+                        // Objects tint the light passing through them once, as
+                        // it enters, like they do shadow rays.
+                        if !computations.inside {
+                            power = power * material.color_at(computations.object.clone(), computations.point);
+                        }
+
+                        Ray::new(computations.under_point, direction)
+                    },
+                    None => Ray::new(computations.over_point, computations.reflect_vector),
+                }
+            };
+            specular = true;
+        }
+    }
+}
+
+/// Photons stored in a balanced kd-tree. The tree is kept implicitly: the
+/// median of each range of photons is its root, split along `axes`.
+#[derive(Debug, Clone)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+    pub gather_radius: f64,
+    /// The IDs of the objects photons were aimed at.
+    pub targets: Vec<i32>,
+}
+
+impl PhotonMap {
+    pub fn new(photons: Vec<Photon>, gather_radius: f64) -> Self {
+        let mut photons = photons;
+        let mut axes = vec![0; photons.len()];
+        Self::balance(&mut photons, &mut axes);
+
+        Self { photons, axes, gather_radius, targets: vec![] }
+    }
+
+    pub fn with_targets(mut self, targets: Vec<i32>) -> Self {
+        self.targets = targets;
+
+        self
+    }
+
+    /// Whether photons carry the light passing through the object, which
+    /// shadow rays should then not let through as well.
+    pub fn carries_light_through(&self, object_id: i32) -> bool {
+        self.targets.contains(&object_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    pub fn photons_within(&self, point: Tuple, radius: f64) -> Vec<&Photon> {
+        let mut found = vec![];
+        Self::gather(&self.photons, &self.axes, point, radius * radius, &mut found);
+
+        found
+    }
+
+    /// Light focused onto the surface by reflective and transparent objects,
+    /// estimated from the density of photons around the point.
+    pub fn radiance(&self, computations: &Computations) -> Color {
+        let material = computations.object.get_material();
+        if material.diffuse <= 0. || self.photons.is_empty() {
+            return BLACK;
+        }
+
+        let mut sum = BLACK;
+        for photon in self.photons_within(computations.point, self.gather_radius) {
+            // Photons landing on the other side of the surface do not light it.
+            if photon.direction.dot(computations.normal_vector) < 0. {
+                sum += photon.power;
+            }
+        }
+
+        let color = material.color_at(computations.object.clone(), computations.point);
+        let area = PI * self.gather_radius * self.gather_radius;
+
+        color * sum * (material.diffuse / area)
+    }
+
+    fn balance(photons: &mut [Photon], axes: &mut [usize]) {
+        if photons.is_empty() {
+            return;
+        }
+
+        // Split along the widest extent of the photons.
+        let mut minimum = [f64::INFINITY; 3];
+        let mut maximum = [f64::NEG_INFINITY; 3];
+        for photon in photons.iter() {
+            for (axis, (low, high)) in minimum.iter_mut().zip(maximum.iter_mut()).enumerate() {
+                let value = coordinate(photon.position, axis);
+                *low = low.min(value);
+                *high = high.max(value);
+            }
+        }
+        let axis = (0..3).max_by(|a, b| {
+            (maximum[*a] - minimum[*a]).partial_cmp(&(maximum[*b] - minimum[*b])).unwrap()
+        }).unwrap();
+
+        let middle = photons.len() / 2;
+        photons.select_nth_unstable_by(middle, |a, b| {
+            coordinate(a.position, axis).partial_cmp(&coordinate(b.position, axis)).unwrap()
+        });
+        axes[middle] = axis;
+
+        let (left, right) = photons.split_at_mut(middle);
+        let (left_axes, right_axes) = axes.split_at_mut(middle);
+        Self::balance(left, left_axes);
+        Self::balance(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    fn gather<'a>(photons: &'a [Photon], axes: &[usize], point: Tuple, radius_squared: f64,
+        found: &mut Vec<&'a Photon>) {
+        if photons.is_empty() {
+            return;
+        }
+
+        let middle = photons.len() / 2;
+        let photon = &photons[middle];
+        let offset = point - photon.position;
+        if offset.dot(offset) <= radius_squared {
+            found.push(photon);
+        }
+
+        let axis = axes[middle];
+        let distance = coordinate(point, axis) - coordinate(photon.position, axis);
+        let (near, near_axes, far, far_axes) = if distance < 0. {
+            (&photons[..middle], &axes[..middle], &photons[middle + 1..], &axes[middle + 1..])
+        } else {
+            (&photons[middle + 1..], &axes[middle + 1..], &photons[..middle], &axes[..middle])
+        };
+
+        Self::gather(near, near_axes, point, radius_squared, found);
+        if distance * distance <= radius_squared {
+            Self::gather(far, far_axes, point, radius_squared, found);
+        }
+    }
+}
+
+fn coordinate(point: Tuple, axis: usize) -> f64 {
+    match axis {
+        0 => point.x,
+        1 => point.y,
+        _ => point.z,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cube::Cube;
+    use super::super::light::{Attenuation, PointLight};
+    use super::super::material::Material;
+    use super::super::plane::Plane;
+    use super::super::shape::Shape;
+    use super::super::sphere::Sphere;
+    use super::super::transformation::*;
+    use super::super::WHITE;
+
+    fn computations_for(world: &World, ray: Ray) -> Computations {
+        let intersections = world.intersect_world(ray);
+
+        Intersection::hit(intersections.clone()).unwrap().prepare_computations(ray, intersections)
+    }
+
+    #[test]
+    fn kd_tree_finds_same_photons_as_brute_force() {
+        let mut rng = Rng::new(4);
+        let photons = (0..500).map(|_| Photon {
+            position: Tuple::point(rng.next_f64(), rng.next_f64() * 2., rng.next_f64() * 0.5),
+            direction: Tuple::vector(0., -1., 0.),
+            power: WHITE,
+        }).collect::<Vec<Photon>>();
+        let map = PhotonMap::new(photons.clone(), 0.2);
+
+        for _ in 0..20 {
+            let point = Tuple::point(rng.next_f64(), rng.next_f64() * 2., rng.next_f64() * 0.5);
+
+            let expected = photons.iter().filter(|photon| {
+                let offset = point - photon.position;
+                offset.dot(offset) <= 0.04
+            }).count();
+
+            let actual = map.photons_within(point, 0.2);
+
+            assert_eq!(expected, actual.len());
+            assert!(actual.iter().all(|photon| (point - photon.position).magnitude() <= 0.2));
+        }
+    }
+
+    #[test]
+    fn diffuse_world_has_no_caustics() {
+        let world: World = Default::default();
+
+        let map = PhotonMapping::new(1000, 0.1).build(&world);
+
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn glass_sphere_focuses_light_below_it() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ball = Shape::Sphere(Sphere::glass_sphere());
+        ball.set_transform(translate(0., 2., 0.));
+        world.objects.push(ball);
+
+        let map = PhotonMapping::new(5000, 0.2).build(&world);
+
+        let below = computations_for(&world, Ray::new(Tuple::point(0., 0.5, 0.), Tuple::vector(0., -1., 0.)));
+        let aside = computations_for(&world, Ray::new(Tuple::point(5., 0.5, 0.), Tuple::vector(0., -1., 0.)));
+
+        assert!(!map.is_empty());
+        assert!(map.radiance(&below).red > 0.);
+        assert_eq!(BLACK, map.radiance(&aside));
+    }
+
+    #[test]
+    fn unlinked_surfaces_receive_no_caustics() {
+        let mut world = World::new();
+        let floor = Shape::Plane(Plane::new());
+        let floor_id = floor.get_id();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE).with_exclude(vec![floor_id]));
+        world.objects.push(floor);
+        let mut ball = Shape::Sphere(Sphere::glass_sphere());
+        ball.set_transform(translate(0., 2., 0.));
+        world.objects.push(ball);
+
+        let map = PhotonMapping::new(5000, 0.2).build(&world);
+
+        let below = computations_for(&world, Ray::new(Tuple::point(0., 0.5, 0.), Tuple::vector(0., -1., 0.)));
+
+        assert_eq!(BLACK, map.radiance(&below));
+    }
+
+    #[test]
+    fn tinted_glass_focuses_tinted_light() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ball = Shape::Sphere(Sphere::glass_sphere());
+        ball.set_material(ball.get_material().with_color(Color::new(1., 0.5, 0.)));
+        ball.set_transform(translate(0., 2., 0.));
+        world.objects.push(ball);
+
+        let map = PhotonMapping::new(5000, 0.2).build(&world);
+        let below = computations_for(&world, Ray::new(Tuple::point(0., 0.5, 0.), Tuple::vector(0., -1., 0.)));
+
+        let actual = map.radiance(&below);
+
+        assert!(actual.red > 0.);
+        assert!((actual.green - actual.red * 0.5).abs() < 1e-9);
+        assert_eq!(0., actual.blue);
+    }
+
+    #[test]
+    fn mirrored_light_matches_direct_lighting() {
+        // A point light between a mirror and a ceiling lights the ceiling once
+        // directly and once again through the mirror, equally brightly.
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 1., 0.), WHITE));
+        let mut mirror = Shape::Cube(Cube::new());
+        mirror.set_material(Material::new().with_reflective(1.).with_diffuse(0.));
+        mirror.set_transform(translate(0., -1., 0.) * scale(5., 1., 5.));
+        world.objects.push(mirror);
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_material(Material::new().with_diffuse(1.).with_ambient(0.).with_specular(0.));
+        ceiling.set_transform(translate(0., 2., 0.));
+        world.objects.push(ceiling);
+
+        let map = PhotonMapping::new(40000, 0.4).build(&world);
+        let computations = computations_for(&world, Ray::new(Tuple::point(0., 1.5, 0.), Tuple::vector(0., 1., 0.)));
+
+        let actual = map.radiance(&computations);
+
+        assert!((actual.red - 1.).abs() < 0.15);
+    }
+
+    #[test]
+    fn mirrored_light_falls_off_over_whole_path() {
+        // The mirrored light travels three times as far as the direct light
+        // before reaching the ceiling.
+        let mut world = World::new();
+        world.lights.push(Light::Point(PointLight::new(Tuple::point(0., 1., 0.), WHITE)
+            .with_attenuation(Attenuation::InverseSquare)));
+        let mut mirror = Shape::Cube(Cube::new());
+        mirror.set_material(Material::new().with_reflective(1.).with_diffuse(0.));
+        mirror.set_transform(translate(0., -1., 0.) * scale(5., 1., 5.));
+        world.objects.push(mirror);
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_material(Material::new().with_diffuse(1.).with_ambient(0.).with_specular(0.));
+        ceiling.set_transform(translate(0., 2., 0.));
+        world.objects.push(ceiling);
+
+        let map = PhotonMapping::new(40000, 0.4).build(&world);
+        let computations = computations_for(&world, Ray::new(Tuple::point(0., 1.5, 0.), Tuple::vector(0., 1., 0.)));
+
+        let expected = 1. / 9.;
+
+        let actual = map.radiance(&computations);
+
+        assert!((actual.red - expected).abs() < 0.15 * expected);
+    }
+}
@@ -0,0 +1,494 @@
+use super::canvas::{Canvas, ImageError};
+use super::color::{Color, TransferFunction};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use std::io::{self, Write};
+
+pub const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+const COMPRESSION_LEVEL: u8 = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PngBitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl PngBitDepth {
+    fn bits(&self) -> u8 {
+        match self {
+            PngBitDepth::Eight => 8,
+            PngBitDepth::Sixteen => 16,
+        }
+    }
+}
+
+pub fn write_png<W: Write>(canvas: &Canvas, writer: &mut W, bit_depth: PngBitDepth,
+    transfer: TransferFunction) -> io::Result<()> {
+    let width = *canvas.get_width();
+    let height = *canvas.get_height();
+
+    writer.write_all(&PNG_SIGNATURE)?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Truecolor, deflate compression, adaptive filtering, no interlacing.
+    header.extend_from_slice(&[bit_depth.bits(), 2, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)?;
+
+    // Tell viewers how the stored values relate to linear light.
+    match transfer {
+        TransferFunction::Srgb => write_chunk(writer, b"sRGB", &[0])?,
+        TransferFunction::Linear => write_chunk(writer, b"gAMA", &100_000_u32.to_be_bytes())?,
+    };
+
+    let bytes_per_pixel = 3 * bit_depth.bits() as usize / 8;
+    let stride = width * bytes_per_pixel;
+    let mut scanlines = Vec::with_capacity(height * (stride + 1));
+    let mut previous = vec![0; stride];
+    for y in 0..height {
+        let mut row = Vec::with_capacity(stride);
+        for x in 0..width {
+            let pixel = canvas.pixel_at(x as u32, y as u32);
+            for channel in [pixel.red, pixel.green, pixel.blue].iter() {
+                let value = transfer.encode(channel.clamp(0., 1.));
+                match bit_depth {
+                    PngBitDepth::Eight => row.push((value * 255.).round() as u8),
+                    PngBitDepth::Sixteen => {
+                        row.extend_from_slice(&((value * 65535.).round() as u16).to_be_bytes())
+                    },
+                }
+            }
+        }
+
+        let (filter, filtered) = filter_row(&row, &previous, bytes_per_pixel);
+        scanlines.push(filter);
+        scanlines.extend_from_slice(&filtered);
+        previous = row;
+    }
+
+    write_chunk(writer, b"IDAT", &compress_to_vec_zlib(&scanlines, COMPRESSION_LEVEL))?;
+    write_chunk(writer, b"IEND", &[])
+}
+
+/// Decodes a non-interlaced PNG of any color type. Alpha is ignored and the
+/// stored values are linearized according to the sRGB or gAMA chunks,
+/// assuming sRGB when neither is present.
+pub fn read_png(data: &[u8]) -> Result<Canvas, ImageError> {
+    if data.len() < PNG_SIGNATURE.len() || data[0..8] != PNG_SIGNATURE {
+        return Err(ImageError::InvalidHeader(String::from("missing PNG signature")));
+    }
+
+    let mut header: Option<Vec<u8>> = None;
+    let mut palette: Vec<u8> = vec![];
+    let mut compressed: Vec<u8> = vec![];
+    let mut gamma: Option<f64> = None;
+    let mut srgb = false;
+
+    let mut position = PNG_SIGNATURE.len();
+    loop {
+        if position + 12 > data.len() {
+            return Err(ImageError::UnexpectedEnd);
+        }
+
+        let length = read_u32(&data[position..]) as usize;
+        let chunk_type = &data[position + 4..position + 8];
+        if position + 12 + length > data.len() {
+            return Err(ImageError::UnexpectedEnd);
+        }
+        let chunk = &data[position + 8..position + 8 + length];
+
+        let mut crc = Crc32::new();
+        crc.update(chunk_type);
+        crc.update(chunk);
+        if crc.finish() != read_u32(&data[position + 8 + length..]) {
+            return Err(ImageError::InvalidData(format!("checksum mismatch in {} chunk",
+                String::from_utf8_lossy(chunk_type))));
+        }
+
+        match chunk_type {
+            b"IHDR" => header = Some(chunk.to_vec()),
+            b"PLTE" => palette = chunk.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"sRGB" => srgb = true,
+            b"gAMA" if length == 4 => gamma = Some(read_u32(chunk) as f64 / 100_000.),
+            b"IEND" => break,
+            _ => (),
+        };
+        position += length + 12;
+    }
+
+    let header = header.ok_or_else(|| ImageError::InvalidHeader(String::from("missing IHDR chunk")))?;
+    if header.len() != 13 {
+        return Err(ImageError::InvalidHeader(String::from("IHDR chunk has the wrong length")));
+    }
+
+    let width = read_u32(&header[0..]) as usize;
+    let height = read_u32(&header[4..]) as usize;
+    let (bit_depth, color_type, interlace) = (header[8] as usize, header[9], header[12]);
+    if interlace != 0 {
+        return Err(ImageError::Unsupported(String::from("interlaced images")));
+    }
+    Canvas::checked_pixel_count(width, height)?;
+
+    let channels = match (color_type, bit_depth) {
+        (0, 1) | (0, 2) | (0, 4) | (0, 8) | (0, 16) => 1,
+        (2, 8) | (2, 16) => 3,
+        (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+        (4, 8) | (4, 16) => 2,
+        (6, 8) | (6, 16) => 4,
+        _ => return Err(ImageError::InvalidHeader(format!("color type {} with bit depth {}",
+            color_type, bit_depth))),
+    };
+    if color_type == 3 && palette.is_empty() {
+        return Err(ImageError::InvalidData(String::from("missing palette")));
+    }
+
+    let scanlines = decompress_to_vec_zlib(&compressed)
+        .map_err(|_| ImageError::InvalidData(String::from("corrupt compressed data")))?;
+    let bits_per_pixel = channels * bit_depth;
+    let too_large = || ImageError::InvalidHeader(format!("{} by {} image is too large", width, height));
+    let stride = width.checked_mul(bits_per_pixel).ok_or_else(too_large)?.div_ceil(8);
+    let bytes_per_pixel = bits_per_pixel.div_ceil(8).max(1);
+    if scanlines.len() < height.checked_mul(stride + 1).ok_or_else(too_large)? {
+        return Err(ImageError::UnexpectedEnd);
+    }
+
+    let max_value = ((1_u32 << bit_depth) - 1) as f64;
+    let decode = |value: u32| {
+        let value = value as f64 / max_value;
+        match gamma {
+            // The chunk stores the exponent used to encode linear values.
+            Some(gamma) if !srgb && gamma > 0. => value.powf(1. / gamma),
+            _ => TransferFunction::Srgb.decode(value),
+        }
+    };
+
+    let mut canvas = Canvas::new(width, height);
+    let mut previous = vec![0; stride];
+    for y in 0..height {
+        let line = &scanlines[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let filter = line[0];
+        if filter > 4 {
+            return Err(ImageError::InvalidData(format!("unknown filter type {}", filter)));
+        }
+
+        let mut row: Vec<u8> = Vec::with_capacity(stride);
+        for (i, value) in line[1..].iter().enumerate() {
+            let left = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+            let up_left = if i >= bytes_per_pixel { previous[i - bytes_per_pixel] } else { 0 };
+            row.push(value.wrapping_add(predict(filter, left, previous[i], up_left)));
+        }
+
+        for x in 0..width {
+            let sample = |channel: usize| -> u32 {
+                let index = x * channels + channel;
+                match bit_depth {
+                    16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]) as u32,
+                    8 => row[index] as u32,
+                    _ => {
+                        let bit = index * bit_depth;
+                        let shift = 8 - bit_depth - bit % 8;
+                        ((row[bit / 8] >> shift) as u32) & ((1 << bit_depth) - 1)
+                    },
+                }
+            };
+
+            let color = match color_type {
+                0 | 4 => {
+                    let gray = decode(sample(0));
+                    Color::new(gray, gray, gray)
+                },
+                3 => {
+                    let index = sample(0) as usize * 3;
+                    if index + 2 >= palette.len() {
+                        return Err(ImageError::InvalidData(String::from("palette index out of range")));
+                    }
+                    let entry = |offset: usize| TransferFunction::Srgb.decode(palette[index + offset] as f64 / 255.);
+                    Color::new(entry(0), entry(1), entry(2))
+                },
+                _ => Color::new(decode(sample(0)), decode(sample(1)), decode(sample(2))),
+            };
+            canvas.write_pixel(x as u32, y as u32, color);
+        }
+
+        previous = row;
+    }
+
+    Ok(canvas)
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(chunk_type)?;
+    writer.write_all(data)?;
+
+    let mut crc = Crc32::new();
+    crc.update(chunk_type);
+    crc.update(data);
+    writer.write_all(&crc.finish().to_be_bytes())
+}
+
+// Tries every filter type and keeps the one with the smallest sum of absolute
+// differences, which is the heuristic recommended by the PNG specification.
+fn filter_row(row: &[u8], previous: &[u8], bytes_per_pixel: usize) -> (u8, Vec<u8>) {
+    (0..=4).map(|filter| {
+        let filtered = row.iter().enumerate().map(|(i, &value)| {
+            let left = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= bytes_per_pixel { previous[i - bytes_per_pixel] } else { 0 };
+
+            value.wrapping_sub(predict(filter, left, up, up_left))
+        }).collect::<Vec<u8>>();
+
+        (filter, filtered)
+    }).min_by_key(|(_, filtered)| filtered.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum::<u64>())
+        .unwrap()
+}
+
+pub fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> u8 {
+    match filter {
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        4 => {
+            let estimate = left as i16 + up as i16 - up_left as i16;
+            let distance_left = (estimate - left as i16).abs();
+            let distance_up = (estimate - up as i16).abs();
+            let distance_up_left = (estimate - up_left as i16).abs();
+
+            if distance_left <= distance_up && distance_left <= distance_up_left {
+                left
+            } else if distance_up <= distance_up_left {
+                up
+            } else {
+                up_left
+            }
+        },
+        _ => 0,
+    }
+}
+
+pub struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self { value: 0xffff_ffff }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.value ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.value & 1).wrapping_neg();
+                self.value = (self.value >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        self.value ^ 0xffff_ffff
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::color::Color;
+    use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+    fn chunks(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut chunks = vec![];
+        let mut position = PNG_SIGNATURE.len();
+        while position < data.len() {
+            let length = u32::from_be_bytes([data[position], data[position + 1],
+                data[position + 2], data[position + 3]]) as usize;
+            let name = String::from_utf8(data[position + 4..position + 8].to_vec()).unwrap();
+            chunks.push((name, data[position + 8..position + 8 + length].to_vec()));
+            position += length + 12;
+        }
+
+        chunks
+    }
+
+    #[test]
+    fn crc_matches_reference_value() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+
+        let expected = 0xcbf4_3926;
+
+        let actual = crc.finish();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn png_starts_with_signature_and_header() {
+        let canvas = Canvas::new(5, 3);
+        let mut data = vec![];
+        write_png(&canvas, &mut data, PngBitDepth::Eight, TransferFunction::Srgb).unwrap();
+
+        let expected_header = vec![0, 0, 0, 5, 0, 0, 0, 3, 8, 2, 0, 0, 0];
+
+        let actual = chunks(&data);
+
+        assert_eq!(PNG_SIGNATURE, data[0..8]);
+        assert_eq!("IHDR", actual[0].0);
+        assert_eq!(expected_header, actual[0].1);
+        assert_eq!("IEND", actual.last().unwrap().0);
+    }
+
+    #[test]
+    fn png_declares_transfer_function() {
+        let canvas = Canvas::new(1, 1);
+        let mut srgb = vec![];
+        let mut linear = vec![];
+        write_png(&canvas, &mut srgb, PngBitDepth::Eight, TransferFunction::Srgb).unwrap();
+        write_png(&canvas, &mut linear, PngBitDepth::Eight, TransferFunction::Linear).unwrap();
+
+        assert!(chunks(&srgb).iter().any(|(name, _)| name == "sRGB"));
+        assert!(chunks(&linear).iter().any(|(name, _)| name == "gAMA"));
+    }
+
+    #[test]
+    fn png_pixel_data_is_encoded_and_filtered() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas.write_pixel(0, 0, Color::new(1., 0.5, 0.));
+        canvas.write_pixel(1, 1, Color::new(1.5, -0.5, 0.2));
+        let mut data = vec![];
+        write_png(&canvas, &mut data, PngBitDepth::Eight, TransferFunction::Linear).unwrap();
+
+        let idat = chunks(&data).into_iter().find(|(name, _)| name == "IDAT").unwrap().1;
+        let scanlines = decompress_to_vec_zlib(&idat).unwrap();
+
+        let expected = vec![vec![255, 128, 0, 0, 0, 0], vec![0, 0, 0, 255, 0, 51]];
+
+        let mut previous = vec![0; 6];
+        for (row, expected_row) in scanlines.chunks(7).zip(expected) {
+            let mut actual_row: Vec<u8> = vec![];
+            for (i, value) in row[1..].iter().enumerate() {
+                let left = if i >= 3 { actual_row[i - 3] } else { 0 };
+                let up_left = if i >= 3 { previous[i - 3] } else { 0 };
+                actual_row.push(value.wrapping_add(predict(row[0], left, previous[i], up_left)));
+            }
+
+            assert_eq!(expected_row, actual_row);
+            previous = actual_row;
+        }
+    }
+
+    #[test]
+    fn sixteen_bit_png_stores_two_bytes_per_channel() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.write_pixel(0, 0, Color::new(1., 0., 0.5));
+        let mut data = vec![];
+        write_png(&canvas, &mut data, PngBitDepth::Sixteen, TransferFunction::Linear).unwrap();
+
+        let idat = chunks(&data).into_iter().find(|(name, _)| name == "IDAT").unwrap().1;
+        let scanlines = decompress_to_vec_zlib(&idat).unwrap();
+
+        let expected = vec![0, 255, 255, 0, 0, 128, 0];
+
+        assert_eq!(16, chunks(&data)[0].1[8]);
+        assert_eq!(expected, scanlines);
+    }
+
+    #[test]
+    fn reading_written_png_round_trips() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(0, 0, Color::new(1., 0.5, 0.));
+        canvas.write_pixel(2, 1, Color::new(0.25, 0.75, 0.1));
+
+        for bit_depth in [PngBitDepth::Eight, PngBitDepth::Sixteen].iter() {
+            for transfer in [TransferFunction::Linear, TransferFunction::Srgb].iter() {
+                let mut data = vec![];
+                write_png(&canvas, &mut data, *bit_depth, *transfer).unwrap();
+
+                let actual = read_png(&data).unwrap();
+
+                let tolerance = if *bit_depth == PngBitDepth::Eight { 0.005 } else { 0.0001 };
+                for y in 0..2 {
+                    for x in 0..3 {
+                        let expected_pixel = canvas.pixel_at(x, y);
+                        let actual_pixel = actual.pixel_at(x, y);
+
+                        assert!((expected_pixel.red - actual_pixel.red).abs() < tolerance);
+                        assert!((expected_pixel.green - actual_pixel.green).abs() < tolerance);
+                        assert!((expected_pixel.blue - actual_pixel.blue).abs() < tolerance);
+                    }
+                }
+            }
+        }
+    }
+
+    fn build_png(header: &[u8], chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut data, b"IHDR", header).unwrap();
+        for (name, chunk) in chunks {
+            write_chunk(&mut data, name, chunk).unwrap();
+        }
+        write_chunk(&mut data, b"IEND", &[]).unwrap();
+
+        data
+    }
+
+    #[test]
+    fn reading_palette_and_grayscale_png() {
+        // A 2x1 palette image with two bits per pixel: indices 1 and 0.
+        let palette_header = [0, 0, 0, 2, 0, 0, 0, 1, 2, 3, 0, 0, 0];
+        let palette_data = build_png(&palette_header, &[
+            (b"PLTE", vec![0, 0, 0, 255, 255, 255]),
+            (b"IDAT", compress_to_vec_zlib(&[0, 0b0100_0000], 6)),
+        ]);
+
+        // A 1x1 sixteen bit grayscale image with alpha stored linearly.
+        let gray_header = [0, 0, 0, 1, 0, 0, 0, 1, 16, 4, 0, 0, 0];
+        let gray_data = build_png(&gray_header, &[
+            (b"gAMA", 100_000_u32.to_be_bytes().to_vec()),
+            (b"IDAT", compress_to_vec_zlib(&[0, 128, 0, 255, 255], 6)),
+        ]);
+
+        let actual_palette = read_png(&palette_data).unwrap();
+        let actual_gray = read_png(&gray_data).unwrap();
+
+        assert_eq!(Color::new(1., 1., 1.), actual_palette.pixel_at(0, 0));
+        assert_eq!(Color::new(0., 0., 0.), actual_palette.pixel_at(1, 0));
+        assert_eq!(Color::new(0.50001, 0.50001, 0.50001), actual_gray.pixel_at(0, 0));
+    }
+
+    #[test]
+    fn reading_malformed_png_returns_errors() {
+        let canvas = Canvas::new(2, 2);
+        let mut data = vec![];
+        write_png(&canvas, &mut data, PngBitDepth::Eight, TransferFunction::Srgb).unwrap();
+
+        let mut corrupted = data.clone();
+        corrupted[20] ^= 0xff;
+        let truncated = &data[0..data.len() - 20];
+        let interlaced = build_png(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 1], &[]);
+        let huge = build_png(&[255, 255, 255, 255, 255, 255, 255, 255, 16, 6, 0, 0, 0], &[]);
+
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("missing PNG signature"))),
+            read_png(b"not a png").map(|_| ()));
+        assert_eq!(Err(ImageError::InvalidData(String::from("checksum mismatch in IHDR chunk"))),
+            read_png(&corrupted).map(|_| ()));
+        assert_eq!(Err(ImageError::UnexpectedEnd), read_png(truncated).map(|_| ()));
+        assert_eq!(Err(ImageError::Unsupported(String::from("interlaced images"))),
+            read_png(&interlaced).map(|_| ()));
+        assert_eq!(Err(ImageError::InvalidHeader(String::from("4294967295 by 4294967295 image is too large"))),
+            read_png(&huge).map(|_| ()));
+    }
+}
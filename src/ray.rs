@@ -5,17 +5,26 @@ use super::tuple::Tuple;
 pub struct Ray {
     pub origin: Tuple,
     pub direction: Tuple,
+    /// The wavelength in nanometres the ray is traced at, or None for a ray
+    /// carrying every color.
+    pub wavelength: Option<f64>,
 }
 
 impl PartialEq for Ray {
     fn eq(&self, other: &Self) -> bool {
-        self.origin == other.origin && self.direction == other.direction
+        self.origin == other.origin && self.direction == other.direction && self.wavelength == other.wavelength
     }
 }
 
 impl Ray {
     pub fn new(origin: Tuple, direction: Tuple) -> Self {
-        Self { origin, direction }
+        Self { origin, direction, wavelength: None }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Self {
+        self.wavelength = wavelength;
+
+        self
     }
 
     pub fn position(&self, t: f64) -> Tuple {
@@ -26,7 +35,7 @@ impl Ray {
         let origin_transform = transformation.clone() * self.origin;
         let direction_transform = transformation * self.direction;
 
-        Ray::new(origin_transform, direction_transform)
+        Ray::new(origin_transform, direction_transform).with_wavelength(self.wavelength)
     }
 }
 
@@ -83,6 +92,17 @@ mod tests {
         assert_eq!(expected.direction, actual.direction);
     }
 
+    #[test]
+    fn transforming_ray_keeps_wavelength() {
+        let ray = Ray::new(Tuple::point(1., 2., 3.), Tuple::vector(0., 1., 0.)).with_wavelength(Some(550.));
+
+        let expected = Some(550.);
+
+        let actual = ray.transform(translate(3., 4., 5.)).wavelength;
+
+        assert_eq!(expected, actual);
+    }
+
     #[test]
     fn scaling_ray() {
         let ray = Ray::new(Tuple::point(1., 2., 3.), Tuple::vector(0., 1., 0.));
@@ -0,0 +1,444 @@
+use super::random::Rng;
+use super::tuple::Tuple;
+use std::f64::consts::PI;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplePattern {
+    Regular,
+    Jittered,
+    RotatedGrid,
+    Halton,
+}
+
+impl SamplePattern {
+    /// Generates `count` sample offsets inside the unit square. The grid based
+    /// patterns split the square into `count` cells of equal area, laid out in
+    /// rows whose lengths differ by at most one when `count` is not a perfect
+    /// square.
+    pub fn offsets(&self, count: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+        let count = count.max(1);
+
+        match self {
+            SamplePattern::Halton => {
+                // A random toroidal shift decorrelates neighbouring pixels.
+                let shift_x = rng.next_f64();
+                let shift_y = rng.next_f64();
+
+                (1..=count).map(|i| ((radical_inverse(2, i) + shift_x) % 1.,
+                    (radical_inverse(3, i) + shift_y) % 1.)).collect()
+            },
+            _ => self.grid(count, rng),
+        }
+    }
+
+    fn grid(&self, count: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+        let rows = (count as f64).sqrt().floor() as usize;
+        let mut offsets = Vec::with_capacity(count);
+
+        for row in 0..rows {
+            let columns = count / rows + if row < count % rows { 1 } else { 0 };
+            let before = offsets.len();
+            for column in 0..columns {
+                // Where the sample falls inside its cell.
+                let (u, v) = match self {
+                    SamplePattern::Jittered => (rng.next_f64(), rng.next_f64()),
+                    // Moving each sample across its cell by its row and down
+                    // it by its column tilts the grid, giving every sample
+                    // its own row and column like a rotated grid, which
+                    // helps on near-horizontal edges.
+                    SamplePattern::RotatedGrid => (((rows - 1 - row) as f64 + 0.5) / rows as f64,
+                        (column as f64 + 0.5) / columns as f64),
+                    _ => (0.5, 0.5),
+                };
+
+                offsets.push(((column as f64 + u) / columns as f64,
+                    (before as f64 + v * columns as f64) / count as f64));
+            }
+        }
+
+        offsets
+    }
+}
+
+pub fn radical_inverse(base: usize, index: usize) -> f64 {
+    let mut result = 0.;
+    let mut fraction = 1. / base as f64;
+    let mut index = index;
+
+    while index > 0 {
+        result += (index % base) as f64 * fraction;
+        index /= base;
+        fraction /= base as f64;
+    }
+
+    result
+}
+
+/// Settings for contrast driven anti-aliasing: a pixel is subdivided when it
+/// differs from a neighbour by more than `threshold` in any color channel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AdaptiveSampling {
+    pub threshold: f64,
+    pub max_depth: u32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(threshold: f64, max_depth: u32) -> Self {
+        Self { threshold, max_depth }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReconstructionFilter {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+}
+
+impl ReconstructionFilter {
+    /// The distance, in pixels, from the pixel center to the edge of the
+    /// filter's support.
+    pub fn radius(&self) -> f64 {
+        match self {
+            ReconstructionFilter::Box => 0.5,
+            ReconstructionFilter::Tent => 1.,
+            ReconstructionFilter::Gaussian => 1.5,
+            ReconstructionFilter::Mitchell => 2.,
+        }
+    }
+
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, d: f64) -> f64 {
+        let radius = self.radius();
+        let d = d.abs();
+        if d > radius {
+            return 0.;
+        }
+
+        match self {
+            ReconstructionFilter::Box => 1.,
+            ReconstructionFilter::Tent => radius - d,
+            ReconstructionFilter::Gaussian => {
+                const ALPHA: f64 = 2.;
+
+                (-ALPHA * d * d).exp() - (-ALPHA * radius * radius).exp()
+            },
+            ReconstructionFilter::Mitchell => {
+                const B: f64 = 1. / 3.;
+                const C: f64 = 1. / 3.;
+
+                // The Mitchell-Netravali kernel is defined on [-2, 2].
+                if d < 1. {
+                    ((12. - 9. * B - 6. * C) * d.powi(3) + (-18. + 12. * B + 6. * C) * d.powi(2) +
+                        (6. - 2. * B)) / 6.
+                } else {
+                    ((-B - 6. * C) * d.powi(3) + (6. * B + 30. * C) * d.powi(2) +
+                        (-12. * B - 48. * C) * d + (8. * B + 24. * C)) / 6.
+                }
+            },
+        }
+    }
+}
+
+/// Maps a point in the unit square to a point on the unit disk, preserving
+/// relative areas (Shirley's concentric mapping).
+pub fn concentric_disk(u: f64, v: f64) -> (f64, f64) {
+    let x = 2. * u - 1.;
+    let y = 2. * v - 1.;
+    if x == 0. && y == 0. {
+        return (0., 0.);
+    }
+
+    let (radius, theta) = if x.abs() > y.abs() {
+        (x, (PI / 4.) * (y / x))
+    } else {
+        (y, (PI / 2.) - (PI / 4.) * (x / y))
+    };
+
+    (radius * theta.cos(), radius * theta.sin())
+}
+
+/// Maps a point in the unit square uniformly onto a regular polygon with the
+/// given number of sides, inscribed in the unit circle.
+pub fn regular_polygon(sides: u32, u: f64, v: f64) -> (f64, f64) {
+    let sides = sides.max(3);
+    let scaled = u * sides as f64;
+    let side = (scaled.floor() as u32).min(sides - 1);
+    let u = scaled - side as f64;
+
+    let angle = 2. * PI / sides as f64;
+    let (x1, y1) = ((side as f64 * angle).cos(), (side as f64 * angle).sin());
+    let (x2, y2) = (((side + 1) as f64 * angle).cos(), ((side + 1) as f64 * angle).sin());
+
+    // Uniformly sample the triangle between the center and this side.
+    let a = u.sqrt();
+    (a * (1. - v) * x1 + a * v * x2, a * (1. - v) * y1 + a * v * y2)
+}
+
+/// Two unit vectors perpendicular to `normal` and to each other.
+pub fn orthonormal_basis(normal: Tuple) -> (Tuple, Tuple) {
+    let helper = if normal.x.abs() > 0.9 {
+        Tuple::vector(0., 1., 0.)
+    } else {
+        Tuple::vector(1., 0., 0.)
+    };
+    let tangent = normal.cross(helper).normalize();
+    let bitangent = normal.cross(tangent);
+
+    (tangent, bitangent)
+}
+
+/// Maps a point in the unit square to a direction in the hemisphere around
+/// `normal`, with a density proportional to the cosine of the angle from it.
+pub fn cosine_hemisphere(normal: Tuple, u: f64, v: f64) -> Tuple {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    let (x, y) = concentric_disk(u, v);
+    let z = (1. - x * x - y * y).max(0.).sqrt();
+
+    (tangent * x + bitangent * y + normal * z).normalize()
+}
+
+/// Maps a point in the unit square to a direction within `cos_max` of
+/// `axis`, uniformly over the solid angle of the cone. A `cos_max` of -1
+/// covers the whole sphere.
+pub fn uniform_cone(axis: Tuple, cos_max: f64, u: f64, v: f64) -> Tuple {
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    let cos_theta = 1. - u * (1. - cos_max);
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let phi = 2. * PI * v;
+
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + axis * cos_theta).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::EPSILON;
+    use super::super::near_eq;
+
+    #[test]
+    fn grid_patterns_generate_exact_sample_count() {
+        let mut rng = Rng::new(1);
+
+        let expected = 5;
+
+        let actual_regular = SamplePattern::Regular.offsets(5, &mut rng);
+        let actual_jittered = SamplePattern::Jittered.offsets(5, &mut rng);
+        let actual_rotated = SamplePattern::RotatedGrid.offsets(5, &mut rng);
+
+        assert_eq!(expected, actual_regular.len());
+        assert_eq!(expected, actual_jittered.len());
+        assert_eq!(expected, actual_rotated.len());
+    }
+
+    #[test]
+    fn regular_pattern_places_samples_at_cell_centers() {
+        let mut rng = Rng::new(1);
+
+        let expected = vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)];
+
+        let actual = SamplePattern::Regular.offsets(4, &mut rng);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn regular_pattern_cells_have_equal_area() {
+        let mut rng = Rng::new(1);
+
+        let expected = vec![(1. / 6., 0.3), (0.5, 0.3), (5. / 6., 0.3), (0.25, 0.8), (0.75, 0.8)];
+
+        let actual = SamplePattern::Regular.offsets(5, &mut rng);
+
+        for ((expected_u, expected_v), (u, v)) in expected.into_iter().zip(actual) {
+            assert!(near_eq(expected_u, u));
+            assert!(near_eq(expected_v, v));
+        }
+    }
+
+    #[test]
+    fn rotated_grid_of_four_matches_classic_pattern() {
+        let mut rng = Rng::new(1);
+
+        let expected = vec![(0.375, 0.125), (0.875, 0.375), (0.125, 0.625), (0.625, 0.875)];
+
+        let actual = SamplePattern::RotatedGrid.offsets(4, &mut rng);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rotated_grid_gives_every_sample_its_own_row_and_column() {
+        let mut rng = Rng::new(1);
+
+        let actual = SamplePattern::RotatedGrid.offsets(16, &mut rng);
+
+        for (i, (u, v)) in actual.iter().enumerate() {
+            for (other_u, other_v) in actual.iter().skip(i + 1) {
+                assert!(!near_eq(*u, *other_u));
+                assert!(!near_eq(*v, *other_v));
+            }
+        }
+    }
+
+    #[test]
+    fn all_patterns_stay_inside_unit_square() {
+        let patterns = [SamplePattern::Regular, SamplePattern::Jittered,
+            SamplePattern::RotatedGrid, SamplePattern::Halton];
+        let mut rng = Rng::new(3);
+
+        for pattern in patterns.iter() {
+            for (u, v) in pattern.offsets(16, &mut rng).into_iter().chain(pattern.offsets(7, &mut rng)) {
+                assert!((0. ..1.).contains(&u));
+                assert!((0. ..1.).contains(&v));
+            }
+        }
+    }
+
+    #[test]
+    fn jittered_pattern_is_reproducible_with_seed() {
+        let expected = SamplePattern::Jittered.offsets(16, &mut Rng::new(99));
+
+        let actual = SamplePattern::Jittered.offsets(16, &mut Rng::new(99));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn radical_inverse_produces_halton_sequence() {
+        assert!(near_eq(0.5, radical_inverse(2, 1)));
+        assert!(near_eq(0.25, radical_inverse(2, 2)));
+        assert!(near_eq(0.75, radical_inverse(2, 3)));
+        assert!(near_eq(1. / 3., radical_inverse(3, 1)));
+        assert!(near_eq(2. / 3., radical_inverse(3, 2)));
+        assert!(near_eq(1. / 9., radical_inverse(3, 3)));
+    }
+
+    #[test]
+    fn box_filter_is_constant_inside_pixel() {
+        let filter = ReconstructionFilter::Box;
+
+        assert!(near_eq(1., filter.weight(0., 0.)));
+        assert!(near_eq(1., filter.weight(0.4, -0.4)));
+        assert!(near_eq(0., filter.weight(0.6, 0.)));
+    }
+
+    #[test]
+    fn tent_filter_falls_off_linearly() {
+        let filter = ReconstructionFilter::Tent;
+
+        assert!(near_eq(1., filter.weight(0., 0.)));
+        assert!(near_eq(0.5, filter.weight(0.5, 0.)));
+        assert!(near_eq(0., filter.weight(1., 0.)));
+    }
+
+    #[test]
+    fn gaussian_filter_is_symmetric_and_vanishes_at_radius() {
+        let filter = ReconstructionFilter::Gaussian;
+
+        assert!(near_eq(filter.weight(0.7, 0.2), filter.weight(-0.7, -0.2)));
+        assert!(filter.weight(0., 0.) > filter.weight(0.5, 0.));
+        assert!(near_eq(0., filter.weight(1.5, 0.)));
+    }
+
+    #[test]
+    fn mitchell_filter_has_negative_lobes() {
+        let filter = ReconstructionFilter::Mitchell;
+
+        assert!(near_eq(8. / 9., filter.weight_1d(0.)));
+        assert!(filter.weight_1d(1.5) < 0.);
+        assert!(near_eq(0., filter.weight_1d(2.)));
+    }
+
+    #[test]
+    fn concentric_mapping_stays_on_disk() {
+        let mut rng = Rng::new(5);
+
+        for _ in 0..100 {
+            let (x, y) = concentric_disk(rng.next_f64(), rng.next_f64());
+
+            assert!(x * x + y * y <= 1. + EPSILON);
+        }
+    }
+
+    #[test]
+    fn concentric_mapping_sends_center_to_center() {
+        let expected = (0., 0.);
+
+        let actual = concentric_disk(0.5, 0.5);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn polygon_mapping_stays_inside_polygon() {
+        let mut rng = Rng::new(8);
+        let sides = 6;
+        // The distance from the center to the middle of each edge.
+        let apothem = (PI / sides as f64).cos();
+
+        for _ in 0..200 {
+            let (x, y) = regular_polygon(sides, rng.next_f64(), rng.next_f64());
+            let angle = y.atan2(x).rem_euclid(2. * PI / sides as f64) - PI / sides as f64;
+
+            assert!((x * x + y * y).sqrt() * angle.cos() <= apothem + EPSILON);
+        }
+    }
+
+    #[test]
+    fn orthonormal_basis_is_perpendicular() {
+        let normals = [Tuple::vector(0., 1., 0.), Tuple::vector(1., 0., 0.),
+            Tuple::vector(1., 2., 3.).normalize()];
+
+        for normal in normals.iter() {
+            let (tangent, bitangent) = orthonormal_basis(*normal);
+
+            assert!(near_eq(0., tangent.dot(*normal)));
+            assert!(near_eq(0., bitangent.dot(*normal)));
+            assert!(near_eq(0., tangent.dot(bitangent)));
+            assert!(near_eq(1., tangent.magnitude()));
+            assert!(near_eq(1., bitangent.magnitude()));
+        }
+    }
+
+    #[test]
+    fn cosine_hemisphere_samples_point_along_normal() {
+        let mut rng = Rng::new(12);
+        let normal = Tuple::vector(0., 0., -1.);
+        let mut sum = 0.;
+
+        for _ in 0..2000 {
+            let direction = cosine_hemisphere(normal, rng.next_f64(), rng.next_f64());
+
+            assert!(near_eq(1., direction.magnitude()));
+            assert!(direction.dot(normal) >= 0.);
+            sum += direction.dot(normal);
+        }
+
+        // The mean cosine of a cosine weighted hemisphere is 2/3.
+        assert!((sum / 2000. - 2. / 3.).abs() < 0.02);
+    }
+
+    #[test]
+    fn uniform_cone_samples_stay_within_cone() {
+        let mut rng = Rng::new(13);
+        let axis = Tuple::vector(1., 2., 3.).normalize();
+        let cos_max = 0.8;
+        let mut sum = 0.;
+
+        for _ in 0..2000 {
+            let direction = uniform_cone(axis, cos_max, rng.next_f64(), rng.next_f64());
+
+            assert!(near_eq(1., direction.magnitude()));
+            assert!(direction.dot(axis) >= cos_max - EPSILON);
+            sum += direction.dot(axis);
+        }
+
+        // Uniform over solid angle, so the cosine is uniform in [cos_max, 1].
+        assert!((sum / 2000. - 0.9).abs() < 0.01);
+    }
+}
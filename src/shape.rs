@@ -20,6 +20,7 @@ use super::tuple::Tuple;
 pub static mut SAVED_RAY: Ray = Ray {
     origin: Tuple { x: 0., y: 0., z: 0., w: 0. },
     direction: Tuple { x: 0., y: 0., z: 0., w: 0. },
+    wavelength: None,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -62,6 +63,7 @@ pub trait CommonShape {
     fn add_child(&mut self, shape: &mut Shape);
     fn world_to_object(&self, point: Tuple) -> Tuple;
     fn normal_to_world(&self, normal: Tuple) -> Tuple;
+    fn object_to_world(&self, point: Tuple) -> Tuple;
     fn includes(&self, other: Shape) -> bool;
     fn bounds_of(&self) -> Bound;
     fn parent_space_bounds_of(&self) -> Bound;
@@ -419,6 +421,20 @@ impl CommonShape for Shape {
         }
     }
 
+    fn object_to_world(&self, point: Tuple) -> Tuple {
+        let parent_point = self.get_transform() * point;
+
+        let parent = self.get_parent();
+        match parent {
+            Some(parent) => {
+                let parent_references = PARENT_REFERENCES.read().unwrap();
+                let parent_shape = parent_references.iter().find(|pr| pr.get_id() == parent).unwrap();
+                parent_shape.object_to_world(parent_point)
+            },
+            None => parent_point,
+        }
+    }
+
     fn includes(&self, other: Shape) -> bool {
         match self {
             Shape::Sphere(sphere) => *sphere.get_id() == other.get_id(),
@@ -692,6 +708,24 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn converting_point_from_object_space_to_world_space() {
+        let mut group1 = Shape::Group(Group::new());
+        group1.set_transform(rotate(PI / 2., Axis::Y));
+        let mut group2 = Shape::Group(Group::new());
+        group2.set_transform(scale(2., 2., 2.));
+        group1.add_child(&mut group2);
+        let mut shape = Shape::Sphere(Sphere::new());
+        shape.set_transform(translate(5., 0., 0.));
+        group2.add_child(&mut shape);
+
+        let expected = Tuple::point(-2., 0., -10.);
+
+        let actual = shape.object_to_world(Tuple::point(0., 0., -1.));
+
+        assert_eq!(expected, actual);
+    }
+
     #[test]
     fn converting_normal_from_object_to_world_space() {
         let mut group1 = Shape::Group(Group::new());
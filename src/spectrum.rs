@@ -0,0 +1,146 @@
+use super::BLACK;
+use super::color::Color;
+use lazy_static::lazy_static;
+
+/// The range of visible wavelengths sampled, in nanometres.
+pub const SHORTEST_WAVELENGTH: f64 = 380.;
+pub const LONGEST_WAVELENGTH: f64 = 780.;
+
+lazy_static! {
+    // The average color of the visible spectrum, so that sampling every
+    // wavelength equally adds up to white.
+    static ref SPECTRUM_AVERAGE: Color = {
+        let steps = 1000;
+        let sum = (0..steps).fold(BLACK, |sum, i| {
+            sum + wavelength_to_color(sample_wavelength((i as f64 + 0.5) / steps as f64))
+        });
+
+        sum * (1. / steps as f64)
+    };
+}
+
+/// How the refractive index of a material changes with the wavelength of
+/// light, which splits white light into its colors. The coefficients take
+/// wavelengths in micrometres, as they are usually published.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ bᵢλ² / (λ² - cᵢ)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Borosilicate crown glass, the most common optical glass.
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653] }
+    }
+
+    /// The refractive index for light of the given wavelength in nanometres.
+    pub fn refractive_index(&self, wavelength: f64) -> f64 {
+        let micrometres = wavelength / 1000.;
+        let squared = micrometres * micrometres;
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / squared,
+            Dispersion::Sellmeier { b, c } => {
+                let sum = b.iter().zip(c.iter()).map(|(b, c)| b * squared / (squared - c)).sum::<f64>();
+
+                (1. + sum).sqrt()
+            },
+        }
+    }
+}
+
+/// Maps a point in the unit interval onto the visible spectrum.
+pub fn sample_wavelength(u: f64) -> f64 {
+    SHORTEST_WAVELENGTH + u * (LONGEST_WAVELENGTH - SHORTEST_WAVELENGTH)
+}
+
+/// The linear RGB color of pure light of the given wavelength in
+/// nanometres, using a fit of the CIE 1931 color matching functions. Colors
+/// outside the RGB gamut are clipped to it.
+pub fn wavelength_to_color(wavelength: f64) -> Color {
+    // Piecewise gaussian with different widths either side of the peak.
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let width = if wavelength < mean { below } else { above };
+        let offset = (wavelength - mean) / width;
+
+        (-0.5 * offset * offset).exp()
+    };
+
+    let x = 1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8);
+
+    Color::new((3.2404542 * x - 1.5371385 * y - 0.4985314 * z).max(0.),
+        (-0.9692660 * x + 1.8760108 * y + 0.0415560 * z).max(0.),
+        (0.0556434 * x - 0.2040259 * y + 1.0572252 * z).max(0.))
+}
+
+/// What a sample traced at the given wavelength contributes to each color
+/// channel. Averaged over wavelengths spread evenly across the visible
+/// spectrum, the weights are white.
+pub fn spectral_weight(wavelength: f64) -> Color {
+    let color = wavelength_to_color(wavelength);
+    let average = *SPECTRUM_AVERAGE;
+
+    Color::new(color.red / average.red, color.green / average.green, color.blue / average.blue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::WHITE;
+
+    #[test]
+    fn cauchy_index_falls_with_wavelength() {
+        let dispersion = Dispersion::Cauchy { a: 1.5, b: 0.01 };
+
+        let expected = 1.5 + 0.01 / 0.25;
+
+        let actual = dispersion.refractive_index(500.);
+
+        assert!((expected - actual).abs() < 1e-9);
+        assert!(dispersion.refractive_index(450.) > dispersion.refractive_index(650.));
+    }
+
+    #[test]
+    fn sellmeier_index_of_crown_glass() {
+        let dispersion = Dispersion::bk7();
+
+        let expected = 1.5168;
+
+        let actual = dispersion.refractive_index(587.6);
+
+        assert!((expected - actual).abs() < 1e-4);
+        assert!(dispersion.refractive_index(450.) > dispersion.refractive_index(650.));
+    }
+
+    #[test]
+    fn wavelengths_map_to_their_colors() {
+        let red = wavelength_to_color(650.);
+        let green = wavelength_to_color(530.);
+        let blue = wavelength_to_color(450.);
+
+        assert!(red.red > red.green && red.red > red.blue);
+        assert!(green.green > green.red && green.green > green.blue);
+        assert!(blue.blue > blue.red && blue.blue > blue.green);
+    }
+
+    #[test]
+    fn spectral_weights_average_to_white() {
+        let samples = 4000;
+
+        let expected = WHITE;
+
+        let actual = (0..samples).fold(BLACK, |sum, i| {
+            sum + spectral_weight(sample_wavelength((i as f64 + 0.5) / samples as f64))
+        }) * (1. / samples as f64);
+
+        assert!((expected.red - actual.red).abs() < 0.001);
+        assert!((expected.green - actual.green).abs() < 0.001);
+        assert!((expected.blue - actual.blue).abs() < 0.001);
+    }
+}
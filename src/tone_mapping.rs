@@ -0,0 +1,263 @@
+use super::canvas::Canvas;
+use super::color::{Color, TransferFunction};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMapOperator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard (f64),
+    Aces,
+    Hable,
+}
+
+impl ToneMapOperator {
+    pub fn map(&self, value: f64) -> f64 {
+        let value = value.max(0.);
+
+        match self {
+            ToneMapOperator::Clamp => value,
+            ToneMapOperator::Reinhard => value / (1. + value),
+            // Without a positive white point this is plain Reinhard.
+            ToneMapOperator::ExtendedReinhard(white_point) if *white_point <= 0. => value / (1. + value),
+            ToneMapOperator::ExtendedReinhard(white_point) => {
+                // Values at or above the white point map to 1.
+                if value >= *white_point {
+                    1.
+                } else {
+                    value * (1. + value / (white_point * white_point)) / (1. + value)
+                }
+            },
+            ToneMapOperator::Aces => {
+                // Krzysztof Narkowicz's fit of the ACES filmic curve.
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            },
+            ToneMapOperator::Hable => {
+                const LINEAR_WHITE: f64 = 11.2;
+
+                Self::hable_curve(value) / Self::hable_curve(LINEAR_WHITE)
+            },
+        }
+    }
+
+    // The filmic curve used in Uncharted 2.
+    fn hable_curve(x: f64) -> f64 {
+        const A: f64 = 0.15;
+        const B: f64 = 0.5;
+        const C: f64 = 0.1;
+        const D: f64 = 0.2;
+        const E: f64 = 0.02;
+        const F: f64 = 0.3;
+
+        ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+    }
+}
+
+/// Converts a high dynamic range canvas into one ready for low dynamic range
+/// export. Colors are scaled by the exposure (in stops), compressed by the
+/// operator, clamped to [0, 1] and finally encoded with the transfer function.
+///
+/// The sRGB default suits formats written as they are, such as PPM. PNG
+/// export encodes colors itself, so tone map for it with
+/// `TransferFunction::Linear` and pass the transfer function to `write_png`
+/// instead, or the image is encoded twice.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ToneMapper {
+    pub exposure: f64,
+    pub operator: ToneMapOperator,
+    pub transfer: TransferFunction,
+}
+
+impl ToneMapper {
+    pub fn new() -> Self {
+        Self {
+            exposure: 0.,
+            operator: ToneMapOperator::Reinhard,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> Self {
+        self.exposure = exposure;
+
+        self
+    }
+
+    pub fn with_operator(mut self, operator: ToneMapOperator) -> Self {
+        self.operator = operator;
+
+        self
+    }
+
+    pub fn with_transfer(mut self, transfer: TransferFunction) -> Self {
+        self.transfer = transfer;
+
+        self
+    }
+
+    pub fn map_color(&self, color: Color) -> Color {
+        let scale = 2_f64.powf(self.exposure);
+        let map = |value: f64| {
+            self.transfer.encode(self.operator.map(value * scale).clamp(0., 1.))
+        };
+
+        Color::new(map(color.red), map(color.green), map(color.blue))
+    }
+
+    pub fn apply(&self, canvas: &Canvas) -> Canvas {
+        let mut mapped = Canvas::new(*canvas.get_width(), *canvas.get_height());
+        for y in 0..*canvas.get_height() as u32 {
+            for x in 0..*canvas.get_width() as u32 {
+                mapped.write_pixel(x, y, self.map_color(canvas.pixel_at(x, y)));
+            }
+        }
+
+        mapped
+    }
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::canvas::PpmFormat;
+    use super::super::near_eq;
+    use super::super::png::PngBitDepth;
+
+    #[test]
+    fn reinhard_operator_compresses_highlights() {
+        let operator = ToneMapOperator::Reinhard;
+
+        assert!(near_eq(0., operator.map(0.)));
+        assert!(near_eq(0.5, operator.map(1.)));
+        assert!(near_eq(0.9, operator.map(9.)));
+    }
+
+    #[test]
+    fn extended_reinhard_operator_maps_white_point_to_one() {
+        let operator = ToneMapOperator::ExtendedReinhard(4.);
+
+        assert!(near_eq(1., operator.map(4.)));
+        assert!(operator.map(1.) > ToneMapOperator::Reinhard.map(1.));
+    }
+
+    #[test]
+    fn extended_reinhard_operator_clamps_above_white_point() {
+        let operator = ToneMapOperator::ExtendedReinhard(4.);
+
+        assert!(near_eq(1., operator.map(5.)));
+        assert!(near_eq(1., operator.map(100.)));
+    }
+
+    #[test]
+    fn extended_reinhard_without_white_point_is_plain_reinhard() {
+        for white_point in [0., -2.].iter() {
+            let operator = ToneMapOperator::ExtendedReinhard(*white_point);
+
+            assert!(near_eq(ToneMapOperator::Reinhard.map(3.), operator.map(3.)));
+        }
+    }
+
+    #[test]
+    fn filmic_operators_are_monotonic() {
+        let operators = [ToneMapOperator::Reinhard, ToneMapOperator::ExtendedReinhard(12.),
+            ToneMapOperator::Aces, ToneMapOperator::Hable];
+
+        for operator in operators.iter() {
+            let mut previous = operator.map(0.);
+            for i in 1..100 {
+                let actual = operator.map(i as f64 * 0.1);
+
+                assert!(actual > previous);
+                previous = actual;
+            }
+        }
+    }
+
+    #[test]
+    fn hable_operator_maps_linear_white_to_one() {
+        let operator = ToneMapOperator::Hable;
+
+        assert!(near_eq(0., operator.map(0.)));
+        assert!(near_eq(1., operator.map(11.2)));
+    }
+
+    #[test]
+    fn mapping_a_color_clamps_and_encodes() {
+        let tone_mapper = ToneMapper::new().with_operator(ToneMapOperator::Clamp)
+            .with_transfer(TransferFunction::Linear);
+
+        let expected = Color::new(0., 0.5, 1.);
+
+        let actual = tone_mapper.map_color(Color::new(-0.5, 0.5, 3.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn exposure_is_measured_in_stops() {
+        let tone_mapper = ToneMapper::new().with_operator(ToneMapOperator::Clamp)
+            .with_transfer(TransferFunction::Linear).with_exposure(-2.);
+
+        let expected = Color::new(0.25, 0.5, 1.);
+
+        let actual = tone_mapper.map_color(Color::new(1., 2., 4.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn mapping_a_color_applies_srgb_encoding() {
+        let tone_mapper = ToneMapper::new().with_operator(ToneMapOperator::Reinhard);
+
+        let expected = TransferFunction::Srgb.encode(0.5);
+
+        let actual = tone_mapper.map_color(Color::new(1., 1., 1.));
+
+        assert!(near_eq(expected, actual.red));
+    }
+
+    #[test]
+    fn linear_tone_mapping_for_png_matches_srgb_tone_mapping_for_ppm() {
+        let mut canvas = Canvas::new(3, 1);
+        canvas.write_pixel(0, 0, Color::new(0.2, 0.5, 0.8));
+        canvas.write_pixel(1, 0, Color::new(1.5, 3., 0.05));
+        canvas.write_pixel(2, 0, Color::new(0., 10., 0.3));
+        let mut ppm = vec![];
+        ToneMapper::new().apply(&canvas).write_ppm(&mut ppm, PpmFormat::Binary, 255).unwrap();
+        let mut png = vec![];
+        ToneMapper::new().with_transfer(TransferFunction::Linear).apply(&canvas)
+            .write_png(&mut png, PngBitDepth::Eight, TransferFunction::Srgb).unwrap();
+
+        // Both store the same sRGB encoded samples.
+        let expected = Canvas::from_ppm(&ppm, TransferFunction::Srgb).unwrap();
+
+        let actual = Canvas::from_png(&png).unwrap();
+
+        for x in 0..3 {
+            assert_eq!(expected.pixel_at(x, 0), actual.pixel_at(x, 0));
+        }
+    }
+
+    #[test]
+    fn applying_tone_mapper_to_canvas() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, Color::new(3., 3., 3.));
+        canvas.write_pixel(1, 0, Color::new(1., 0., 0.));
+        let tone_mapper = ToneMapper::new().with_transfer(TransferFunction::Linear);
+
+        let expected_left = Color::new(0.75, 0.75, 0.75);
+        let expected_right = Color::new(0.5, 0., 0.);
+
+        let actual = tone_mapper.apply(&canvas);
+
+        assert_eq!(2, *actual.get_width());
+        assert_eq!(1, *actual.get_height());
+        assert_eq!(expected_left, actual.pixel_at(0, 0));
+        assert_eq!(expected_right, actual.pixel_at(1, 0));
+    }
+}
@@ -1,20 +1,36 @@
 use super::BLACK;
+use super::ambient_occlusion::AmbientOcclusion;
+use super::background::Background;
 use super::color::Color;
 use super::computations::Computations;
 use super::intersection::Intersection;
-use super::light::Light;
-use super::material::Material;
+use super::light::{direction_to, Light, LightPosition, LightTrait, ObjectLight};
+use super::material::{beer_lambert, Material};
+use super::medium::Medium;
 use super::near_eq;
+use super::photon_map::PhotonMap;
+use super::PARENT_REFERENCES;
+use super::random::Rng;
 use super::ray::Ray;
 use super::shape::{Shape, CommonShape};
 use super::sphere::Sphere;
 use super::transformation::*;
 use super::tuple::Tuple;
 use super::WHITE;
+use std::f64::consts::PI;
 
 pub struct World {
     pub objects: Vec<Shape>,
     pub lights: Vec<Light>,
+    pub background: Background,
+    /// Darkens the ambient term of surfaces in creases and corners.
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+    /// Photons focused by reflective and transparent objects, added to
+    /// surfaces as caustics. Transparent objects the photons were aimed at
+    /// then cast full shadows.
+    pub caustics: Option<PhotonMap>,
+    /// The medium filling the space outside every object.
+    pub fog: Option<Medium>,
 }
 
 impl Default for World {
@@ -34,13 +50,15 @@ impl Default for World {
         let lights = vec![light];
         let objects = vec![sphere1, sphere2];
 
-        Self { objects, lights }
+        Self { objects, lights, background: Default::default(), ambient_occlusion: None, caustics: None,
+            fog: None }
     }
 }
 
 impl World {
     pub fn new() -> Self {
-        Self { objects: vec![], lights: vec![] }
+        Self { objects: vec![], lights: vec![], background: Default::default(), ambient_occlusion: None,
+            caustics: None, fog: None }
     }
 
     pub fn intersect_world(&self, ray: Ray) -> Vec<Intersection> {
@@ -55,14 +73,23 @@ impl World {
     }
 
     pub fn shade_hit(&self, computations: Computations, remaining: i32) -> Color {
-        let mut surface = BLACK;
-        let shadowed = self.is_shadowed(computations.over_point);
-        let material = computations.object.get_material();
+        let mut material = computations.object.get_material();
+        let mut surface = material.emission;
+        if let Some(ambient_occlusion) = self.ambient_occlusion {
+            material.ambient *= ambient_occlusion.accessibility(self, &computations);
+        }
 
-        for (light, shadow) in self.lights.iter().zip(shadowed) {
+        let object_ids = Self::object_ids(&computations.object);
+
+        for light in self.lights.iter().filter(|light| light.get_linking().illuminates(&object_ids)) {
+            let transmittance = self.transmittance_at(light, computations.over_point);
             surface += material.lighting(computations.object.clone(),
-                *light, computations.over_point, computations.eye_vector, 
-                computations.normal_vector, shadow);
+                light, computations.over_point, computations.eye_vector, 
+                computations.normal_vector, transmittance);
+        }
+        surface += self.environment_lighting(&computations);
+        if let Some(caustics) = &self.caustics {
+            surface += caustics.radiance(&computations);
         }
         
         let reflected = self.reflected_color(computations.clone(), remaining);
@@ -82,28 +109,311 @@ impl World {
         let intersections = self.intersect_world(ray);
         let hit = Intersection::hit(intersections.clone());
         
-        if hit.is_none() {
-            BLACK
-        } else {
-            self.shade_hit(hit.unwrap().prepare_computations(ray, intersections), remaining)
+        let (color, distance) = match hit {
+            Some(hit) => {
+                let computations = hit.prepare_computations(ray, intersections.clone());
+                let attenuation = computations.attenuation;
+
+                (self.shade_hit(computations, remaining) * attenuation, hit.t)
+            },
+            None => (self.background.color_in(ray.direction), f64::INFINITY),
+        };
+
+        match self.medium_before(&intersections, distance) {
+            Some(medium) => color * medium.transmittance(distance * ray.direction.magnitude()) +
+                medium.in_scattering(self, ray, distance),
+            None => color,
         }
     }
 
     pub fn is_shadowed(&self, point: Tuple) -> Vec<bool> {
         self.lights.iter().map(|light| {
-            let vector = light.position - point;
-            let distance = vector.magnitude();
-            let direction = vector.normalize();
+            light.get_linking().casts_shadows && self.is_shadowed_from(point, light.get_position())
+        }).collect::<Vec<bool>>()
+    }
 
-            let ray = Ray::new(point, direction);
-            let intersections = self.intersect_world(ray);
+    /// Whether anything blocks the path from `point` to the light sample.
+    pub fn is_shadowed_from(&self, point: Tuple, light_position: LightPosition) -> bool {
+        let (direction, distance) = direction_to(light_position, point);
+
+        let ray = Ray::new(point, direction);
+        let intersections = self.intersect_world(ray);
+
+        let hit = Intersection::hit(intersections);
+        match hit {
+            Some(hit) => hit.t < distance && hit.object.get_casts_shadow(),
+            None => false,
+        }
+    }
+
+    /// The light that survives the trip from the light sample to `point`.
+    /// Each shadow casting object in between filters it by its transparency
+    /// and its color where the shadow ray first meets it; an opaque object
+    /// blocks it completely.
+    pub fn transmittance_from(&self, point: Tuple, light_position: LightPosition) -> Color {
+        self.transmittance_ignoring(point, light_position, None)
+    }
 
-            let hit = Intersection::hit(intersections);
-            match hit {
-                Some(hit) => hit.t < distance && hit.object.get_casts_shadow(),
-                None => false,
+    /// Like `transmittance_from`, but the object with ID `ignored`, such as
+    /// the shape emitting the light, lets it through.
+    pub fn transmittance_ignoring(&self, point: Tuple, light_position: LightPosition, ignored: Option<i32>) -> Color {
+        let (direction, distance) = direction_to(light_position, point);
+
+        let ray = Ray::new(point, direction);
+        let intersections = self.intersect_world(ray);
+
+        let mut transmittance = WHITE;
+        let mut filtered_by = vec![];
+        let mut containers = vec![];
+        let mut travelled = 0.;
+        for intersection in intersections.iter() {
+            // The light is dimmed by whatever it crossed since the last
+            // surface.
+            let t = intersection.t.min(distance);
+            if t > travelled {
+                transmittance = transmittance * self.attenuation_inside(&containers, t - travelled);
+                travelled = t;
             }
-        }).collect::<Vec<bool>>()
+
+            if intersection.t >= distance {
+                break;
+            }
+
+            let object = &intersection.object;
+            Self::cross_surface(&mut containers, object);
+            if intersection.t <= 0. {
+                continue;
+            }
+
+            // Both the entry and exit of an object are on the ray, but the
+            // object only filters the light once.
+            if !object.get_casts_shadow() || filtered_by.contains(&object.get_id()) ||
+                ignored == Some(object.get_id()) {
+                continue;
+            }
+            filtered_by.push(object.get_id());
+
+            let material = object.get_material();
+            // Photons carry the light refracted through the transparent
+            // objects they were aimed at once caustics are traced.
+            let carried = self.caustics.as_ref().is_some_and(|caustics| {
+                caustics.carries_light_through(object.get_id())
+            });
+            if carried && material.transparency > 0. {
+                return BLACK;
+            }
+            let color = material.color_at(object.clone(), ray.position(intersection.t));
+            transmittance = transmittance * color * material.transparency;
+            if transmittance == BLACK {
+                return BLACK;
+            }
+        }
+
+        // Light from infinitely far away is not dimmed by the medium it
+        // starts in, or fog would block it entirely.
+        if distance.is_finite() && travelled < distance {
+            transmittance = transmittance * self.attenuation_inside(&containers, distance - travelled);
+        }
+
+        transmittance
+    }
+
+    /// The medium a ray passes through before reaching `t`: that of the
+    /// innermost object containing it, or the fog outside every object.
+    pub fn medium_before(&self, intersections: &[Intersection], t: f64) -> Option<Medium> {
+        let mut containers = vec![];
+        for intersection in intersections.iter().take_while(|i| i.t < t) {
+            Self::cross_surface(&mut containers, &intersection.object);
+        }
+
+        self.medium_inside(&containers)
+    }
+
+    fn medium_inside(&self, containers: &[&Shape]) -> Option<Medium> {
+        match containers.last() {
+            Some(object) => object.get_material().medium,
+            None => self.fog,
+        }
+    }
+
+    // The light left after `length` inside the innermost container, which
+    // absorbs it as well as any medium it holds.
+    fn attenuation_inside(&self, containers: &[&Shape], length: f64) -> Color {
+        let absorbed = match containers.last() {
+            Some(object) => beer_lambert(object.get_material().absorption, length),
+            None => WHITE,
+        };
+
+        match self.medium_inside(containers) {
+            Some(medium) => absorbed * medium.transmittance(length),
+            None => absorbed,
+        }
+    }
+
+    // Enters the object, or leaves it if the ray is already inside.
+    fn cross_surface<'a>(containers: &mut Vec<&'a Shape>, object: &'a Shape) {
+        match containers.iter().position(|container| container.get_id() == object.get_id()) {
+            Some(index) => {
+                containers.remove(index);
+            },
+            None => containers.push(object),
+        }
+    }
+
+    /// The average light reaching `point` over all of the light's samples.
+    pub fn transmittance_at(&self, light: &Light, point: Tuple) -> Color {
+        if light.illumination_at(point) == BLACK {
+            return BLACK;
+        }
+        if !light.get_linking().casts_shadows {
+            return WHITE;
+        }
+
+        let samples = light.sample_points(point);
+        let mut sum = BLACK;
+        for sample in samples.iter() {
+            sum += self.transmittance_ignoring(point, *sample, light.emitter_id());
+        }
+
+        sum * (1. / samples.len() as f64)
+    }
+
+    /// Adds a light for every emissive sphere and triangle in the world,
+    /// including those inside groups, sampled `samples` times per point.
+    /// Other emissive shapes still glow but do not light the scene.
+    pub fn promote_emissive_shapes(&mut self, samples: usize) {
+        let mut emitters = vec![];
+        for object in self.objects.iter() {
+            Self::collect_emitters(object, &mut emitters);
+        }
+
+        // Shapes that cannot be sampled are skipped.
+        for emitter in emitters {
+            if let Some(light) = ObjectLight::new(emitter, samples) {
+                self.lights.push(Light::Object(light));
+            }
+        }
+    }
+
+    fn collect_emitters(object: &Shape, emitters: &mut Vec<Shape>) {
+        match object {
+            Shape::Group(_) => {
+                for child in object.get_shapes() {
+                    Self::collect_emitters(child, emitters);
+                }
+            },
+            _ if object.get_material().emission != BLACK => emitters.push(object.clone()),
+            _ => (),
+        }
+    }
+
+    /// The ID of the object followed by the IDs of the groups containing it.
+    pub fn object_ids(object: &Shape) -> Vec<i32> {
+        let mut ids = vec![object.get_id()];
+        let mut parent = object.get_parent();
+        while let Some(id) = parent {
+            ids.push(id);
+
+            let parent_references = PARENT_REFERENCES.read().unwrap();
+            parent = parent_references.iter().find(|pr| pr.get_id() == id).and_then(|pr| pr.get_parent());
+        }
+
+        ids
+    }
+
+    /// Light arriving directly from the lights and the environment, reflected
+    /// by the diffuse part of the surface alone (a Lambertian BRDF of color
+    /// times diffuse over π). Emissive shapes light the surface in proportion
+    /// to the solid angle they cover, as they would if a ray hit them.
+    pub fn direct_lighting(&self, computations: &Computations) -> Color {
+        let material = computations.object.get_material();
+        if material.diffuse <= 0. {
+            return BLACK;
+        }
+
+        let point = computations.over_point;
+        let object_ids = Self::object_ids(&computations.object);
+
+        let mut irradiance = BLACK;
+        for light in self.lights.iter().filter(|light| light.get_linking().illuminates(&object_ids)) {
+            let illumination = light.illumination_at(point);
+            if illumination == BLACK {
+                continue;
+            }
+
+            let samples = light.sample_points(point);
+            let mut sum = BLACK;
+            for sample in samples.iter() {
+                let (direction, _) = direction_to(*sample, point);
+                let cosine = direction.dot(computations.normal_vector);
+                if cosine <= 0. {
+                    continue;
+                }
+
+                // Lights without a surface deliver π times their intensity,
+                // as in the diffuse term of `Material::lighting`.
+                let solid_angle = match (light, sample) {
+                    (Light::Object(object), LightPosition::Point(position)) => {
+                        object.solid_angle_at(*position, point)
+                    },
+                    _ => PI,
+                };
+                let transmittance = if light.get_linking().casts_shadows {
+                    self.transmittance_ignoring(point, *sample, light.emitter_id())
+                } else {
+                    WHITE
+                };
+
+                sum += transmittance * (cosine * solid_angle);
+            }
+
+            irradiance += illumination * sum * (1. / samples.len() as f64);
+        }
+
+        let color = material.color_at(computations.object.clone(), point);
+
+        color * irradiance * (material.diffuse / PI) + self.environment_lighting(computations)
+    }
+
+    /// Whether the background is an environment map that lights the scene.
+    pub fn samples_environment(&self) -> bool {
+        match &self.background {
+            Background::Environment(environment) => environment.samples > 0,
+            _ => false,
+        }
+    }
+
+    /// Diffuse light from an environment map background, estimated with
+    /// shadow rays importance sampled from the map.
+    pub fn environment_lighting(&self, computations: &Computations) -> Color {
+        let environment = match &self.background {
+            Background::Environment(environment) if environment.samples > 0 => environment,
+            _ => return BLACK,
+        };
+
+        let material = computations.object.get_material();
+        if material.diffuse <= 0. {
+            return BLACK;
+        }
+
+        let point = computations.over_point;
+        let mut rng = Rng::from_values(&[environment.seed, point.x.to_bits(), point.y.to_bits(), point.z.to_bits()]);
+        let mut sum = BLACK;
+        for _ in 0..environment.samples {
+            if let Some((direction, pdf)) = environment.sample(rng.next_f64(), rng.next_f64()) {
+                let cosine = direction.dot(computations.normal_vector);
+                if cosine <= 0. {
+                    continue;
+                }
+
+                let transmittance = self.transmittance_from(point, LightPosition::Infinite(direction));
+                sum += environment.color_in(direction) * transmittance * (cosine / (PI * pdf));
+            }
+        }
+
+        let color = material.color_at(computations.object.clone(), point);
+
+        color * sum * (material.diffuse / environment.samples as f64)
     }
 
     pub fn reflected_color(&self, computations: Computations, remaining: i32) -> Color {
@@ -111,27 +421,38 @@ impl World {
             return BLACK;
         }
 
-        let reflect_ray = Ray::new(computations.over_point, computations.reflect_vector);
+        let reflect_ray = Ray::new(computations.over_point, computations.reflect_vector)
+            .with_wavelength(computations.wavelength);
         let color = self.color_at(reflect_ray, remaining - 1);
 
         color * computations.object.get_material().reflective
     }
 
-    pub fn refracted_color(&self, computations: Computations, remaining: i32) -> Color {
-        if near_eq(computations.object.get_material().transparency, 0.) || remaining <= 0 {
-            return BLACK;
-        }
-
+    /// The direction of the ray refracted through the surface, or `None` for
+    /// total internal reflection.
+    pub fn refracted_direction(computations: &Computations) -> Option<Tuple> {
         let n_ratio = computations.n1 / computations.n2;
         let cos_i = computations.eye_vector.dot(computations.normal_vector);
         let sin2_t = n_ratio.powi(2) * (1. - cos_i.powi(2));
         if sin2_t > 1. {
-            return BLACK;
+            return None;
         }
 
         let cos_t = (1. - sin2_t).sqrt();
-        let direction = computations.normal_vector * (n_ratio * cos_i - cos_t) - computations.eye_vector * n_ratio;
-        let refract_ray = Ray::new(computations.under_point, direction);
+
+        Some(computations.normal_vector * (n_ratio * cos_i - cos_t) - computations.eye_vector * n_ratio)
+    }
+
+    pub fn refracted_color(&self, computations: Computations, remaining: i32) -> Color {
+        if near_eq(computations.object.get_material().transparency, 0.) || remaining <= 0 {
+            return BLACK;
+        }
+
+        let direction = match Self::refracted_direction(&computations) {
+            Some(direction) => direction,
+            None => return BLACK,
+        };
+        let refract_ray = Ray::new(computations.under_point, direction).with_wavelength(computations.wavelength);
 
         self.color_at(refract_ray, remaining - 1) * computations.object.get_material().transparency
     }
@@ -140,15 +461,24 @@ impl World {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::background::{EnvironmentMap, GradientSky};
+    use super::super::canvas::Canvas;
     use super::super::color::Color;
+    use super::super::cube::Cube;
     use super::super::DEFAULT_RECURSION;
+    use super::super::group::Group;
     use super::super::intersections;
+    use super::super::light::{AreaLight, DirectionalLight, SpotLight};
     use super::super::material::Material;
+    use super::super::medium::Medium;
     use super::super::ORIGIN;
     use super::super::pattern::*;
+    use super::super::photon_map::PhotonMapping;
     use super::super::plane::Plane;
     use super::super::ray::Ray;
     use super::super::shape::{Shape, CommonShape};
+    use super::super::spectrum::Dispersion;
+    use super::super::triangle::Triangle;
     use super::super::tuple::Tuple;
 
     #[test]
@@ -245,6 +575,82 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn color_when_ray_misses_uses_background() {
+        let sky = GradientSky::new(Color::new(0., 0., 1.), WHITE, BLACK);
+        let world = World { background: Background::Gradient(sky), ..Default::default() };
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 1., 0.));
+
+        let expected = Color::new(0., 0., 1.);
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn reflections_that_miss_see_background() {
+        let mut world = World::new();
+        world.background = Background::Color(Color::new(0.2, 0.4, 0.6));
+        let mut plane = Shape::Plane(Plane::new());
+        plane.set_material(Material::new().with_color(BLACK).with_ambient(0.).with_diffuse(0.)
+            .with_specular(0.).with_reflective(0.5));
+        world.objects.push(plane);
+        let ray = Ray::new(Tuple::point(0., 1., -1.), Tuple::vector(0., -1., 1.).normalize());
+
+        let expected = Color::new(0.1, 0.2, 0.3);
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn uniform_environment_lights_diffuse_surfaces() {
+        let mut image = Canvas::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                image.write_pixel(x, y, WHITE);
+            }
+        }
+        let mut world = World::new();
+        world.background = Background::Environment(EnvironmentMap::new(image).with_samples(4000));
+        let mut plane = Shape::Plane(Plane::new());
+        plane.set_material(Material::new().with_ambient(0.).with_diffuse(0.5).with_specular(0.));
+        world.objects.push(plane);
+        let ray = Ray::new(Tuple::point(0., 1., -1.), Tuple::vector(0., -1., 1.).normalize());
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert!((actual.red - 0.5).abs() < 0.03);
+        assert!((actual.green - 0.5).abs() < 0.03);
+        assert!((actual.blue - 0.5).abs() < 0.03);
+    }
+
+    #[test]
+    fn environment_lighting_is_shadowed() {
+        let mut image = Canvas::new(8, 4);
+        for x in 0..8 {
+            image.write_pixel(x, 0, WHITE);
+        }
+        let mut world = World::new();
+        world.background = Background::Environment(EnvironmentMap::new(image).with_samples(64));
+        let mut plane = Shape::Plane(Plane::new());
+        plane.set_material(Material::new().with_ambient(0.).with_diffuse(0.5).with_specular(0.));
+        let mut roof = Shape::Plane(Plane::new());
+        roof.set_transform(translate(0., 5., 0.));
+        world.objects.push(plane.clone());
+        let ray = Ray::new(Tuple::point(0., 1., -1.), Tuple::vector(0., -1., 1.).normalize());
+
+        let actual_open = world.color_at(ray, DEFAULT_RECURSION);
+
+        world.objects = vec![plane, roof];
+        let actual_covered = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert!(actual_open.red > 0.);
+        assert_eq!(BLACK, actual_covered);
+    }
+
     #[test]
     fn color_when_ray_hits() {
         let world: World = Default::default();
@@ -305,6 +711,535 @@ mod tests {
         assert!(!world.is_shadowed(point)[0]);
     }
 
+    #[test]
+    fn point_lights_evaluate_light_intensity_at_given_point() {
+        let world: World = Default::default();
+        let light = world.lights[0].clone();
+        let points = [Tuple::point(0., 1.0001, 0.), Tuple::point(-1.0001, 0., 0.),
+            Tuple::point(0., 0., -1.0001), Tuple::point(0., 0., 1.0001), Tuple::point(1.0001, 0., 0.),
+            Tuple::point(0., -1.0001, 0.), ORIGIN];
+
+        let expected = [1., 1., 1., 0., 0., 0., 0.].iter().map(|i| WHITE * *i).collect::<Vec<Color>>();
+
+        let actual = points.iter().map(|point| world.transmittance_at(&light, *point)).collect::<Vec<Color>>();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn area_lights_evaluate_light_intensity_at_given_point() {
+        let world: World = Default::default();
+        let corner = Tuple::point(-0.5, -0.5, -5.);
+        let v1 = Tuple::vector(1., 0., 0.);
+        let v2 = Tuple::vector(0., 1., 0.);
+        let light = Light::Area(AreaLight::rectangle(corner, v1, 2, v2, 2, WHITE).with_jitter(false));
+        let points = [Tuple::point(0., 0., 2.), Tuple::point(1., -1., 2.), Tuple::point(1.5, 0., 2.),
+            Tuple::point(1.25, 1.25, 3.), Tuple::point(0., 0., -2.)];
+
+        let expected = [0., 0.25, 0.5, 0.75, 1.].iter().map(|i| WHITE * *i).collect::<Vec<Color>>();
+
+        let actual = points.iter().map(|point| world.transmittance_at(&light, *point)).collect::<Vec<Color>>();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn jittered_area_lights_give_soft_shadow_edges() {
+        let world: World = Default::default();
+        let corner = Tuple::point(-0.5, -0.5, -5.);
+        let v1 = Tuple::vector(1., 0., 0.);
+        let v2 = Tuple::vector(0., 1., 0.);
+        let light = Light::Area(AreaLight::rectangle(corner, v1, 8, v2, 8, WHITE).with_seed(4));
+        let point = Tuple::point(1.5, 0., 2.);
+
+        let actual = world.transmittance_at(&light, point);
+
+        assert!(actual.red > 0. && actual.red < 1.);
+        assert_eq!(actual, world.transmittance_at(&light, point));
+    }
+
+    #[test]
+    fn spot_lights_cast_shadows_from_their_position() {
+        let mut world: World = Default::default();
+        world.lights[0] = Light::Spot(SpotLight::new(Tuple::point(-10., 10., -10.),
+            Tuple::vector(1., -1., 1.), 0.5, 0.7, WHITE));
+
+        assert!(world.is_shadowed(Tuple::point(10., -10., 10.))[0]);
+        assert!(!world.is_shadowed(Tuple::point(-2., 2., -2.))[0]);
+    }
+
+    #[test]
+    fn points_outside_spot_light_cone_receive_no_light() {
+        let mut world: World = Default::default();
+        world.lights[0] = Light::Spot(SpotLight::new(Tuple::point(-10., 10., -10.),
+            Tuple::vector(-1., 0., 0.), 0.5, 0.7, WHITE));
+        let light = world.lights[0].clone();
+
+        let expected = BLACK;
+
+        let actual = world.transmittance_at(&light, Tuple::point(-2., 2., -2.));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn shading_with_spot_light_outside_cone_leaves_ambient() {
+        let mut world: World = Default::default();
+        world.lights[0] = Light::Spot(SpotLight::new(Tuple::point(0., 0., -10.),
+            Tuple::vector(0., 1., 0.), 0.2, 0.4, WHITE));
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = Color::new(0.08, 0.1, 0.06);
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_ambient_term() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+        let mut floor = Shape::Plane(Plane::new());
+        floor.set_material(Material::new().with_ambient(1.).with_diffuse(0.).with_specular(0.));
+        world.objects.push(floor);
+        let mut ceiling = Shape::Plane(Plane::new());
+        ceiling.set_transform(translate(0., 1., 0.));
+        world.objects.push(ceiling);
+        let ray = Ray::new(Tuple::point(0., 0.5, 0.), Tuple::vector(0., -1., 0.));
+
+        assert_eq!(WHITE, world.color_at(ray, DEFAULT_RECURSION));
+
+        world.ambient_occlusion = Some(AmbientOcclusion::new(16, 100.));
+
+        assert_eq!(BLACK, world.color_at(ray, DEFAULT_RECURSION));
+    }
+
+    #[test]
+    fn caustics_brighten_shadow_of_glass_ball() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ball = Shape::Sphere(Sphere::glass_sphere());
+        ball.set_transform(translate(0., 2., 0.));
+        world.objects.push(ball);
+        let ray = Ray::new(Tuple::point(0., 0.5, 0.), Tuple::vector(0., -1., 0.));
+
+        let without_caustics = world.color_at(ray, DEFAULT_RECURSION);
+        world.caustics = Some(PhotonMapping::new(5000, 0.2).build(&world));
+        let with_caustics = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert!(with_caustics.red > without_caustics.red);
+    }
+
+    #[test]
+    fn transparent_objects_cast_full_shadows_with_caustics() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut ball = Shape::Sphere(Sphere::glass_sphere());
+        ball.set_transform(translate(0., 2., 0.));
+        world.objects.push(ball);
+        let point = Tuple::point(0., 0., 0.);
+
+        assert_eq!(WHITE, world.transmittance_at(&world.lights[0], point));
+
+        world.caustics = Some(PhotonMapping::new(100, 0.2).build(&world));
+
+        assert_eq!(BLACK, world.transmittance_at(&world.lights[0], point));
+    }
+
+    #[test]
+    fn grouped_glass_casts_full_shadow_with_caustics() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut group = Shape::Group(Group::new());
+        group.set_transform(translate(0., 2., 0.));
+        group.set_material(Material::new().with_transparency(1.).with_refractive_index(1.5));
+        let mut ball = Shape::Sphere(Sphere::new());
+        group.add_child(&mut ball);
+        // Unbounded members leave the group as a whole without bounds.
+        let mut skylight = Shape::Plane(Plane::new());
+        skylight.set_transform(translate(0., 20., 0.));
+        group.add_child(&mut skylight);
+        world.objects.push(group);
+        let point = Tuple::point(0., 0., 0.);
+        let ray = Ray::new(Tuple::point(0., 0.5, 0.), Tuple::vector(0., -1., 0.));
+        let intersections = world.intersect_world(ray);
+        let computations = Intersection::hit(intersections.clone()).unwrap().prepare_computations(ray, intersections);
+
+        world.caustics = Some(PhotonMapping::new(1000, 0.2).build(&world));
+
+        assert_eq!(BLACK, world.transmittance_at(&world.lights[0], point));
+        assert!(world.caustics.as_ref().unwrap().radiance(&computations).red > 0.);
+    }
+
+    #[test]
+    fn glass_out_of_reach_of_photons_still_filters_light() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 10., 0.), WHITE));
+        world.objects.push(Shape::Plane(Plane::new()));
+        let mut pane = Shape::Plane(Plane::new());
+        pane.set_material(Material::new().with_color(Color::new(1., 0.5, 0.5)).with_transparency(0.8)
+            .with_refractive_index(1.5));
+        pane.set_transform(translate(0., 2., 0.));
+        world.objects.push(pane);
+        let point = Tuple::point(0., 0., 0.);
+
+        let expected = Color::new(0.8, 0.4, 0.4);
+
+        world.caustics = Some(PhotonMapping::new(1000, 0.2).build(&world));
+        let actual = world.transmittance_at(&world.lights[0], point);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn fog_dims_objects_by_distance() {
+        let mut world = World::new();
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_emission(WHITE));
+        world.objects.push(sphere);
+        world.fog = Some(Medium::new(Color::new(0.1, 0.2, 0.3), BLACK));
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = Color::new((-0.4_f64).exp(), (-0.8_f64).exp(), (-1.2_f64).exp());
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn fog_hides_background() {
+        let world = World { background: Background::Color(WHITE),
+            fog: Some(Medium::new(Color::new(0.01, 0.01, 0.01), BLACK)), ..World::new() };
+        let ray = Ray::new(ORIGIN, Tuple::vector(0., 0., 1.));
+
+        let expected = BLACK;
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn volume_absorbs_light_passing_through_it() {
+        let mut world = World { background: Background::Color(WHITE), ..World::new() };
+        let mut volume = Shape::Sphere(Sphere::new());
+        volume.set_material(Material::new().with_transparency(1.).with_diffuse(0.).with_ambient(0.)
+            .with_specular(0.).with_medium(Medium::new(Color::new(0.5, 0.5, 0.5), BLACK)));
+        world.objects.push(volume);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = WHITE * (-1_f64).exp();
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn media_dim_shadow_rays() {
+        let mut world = World::new();
+        let mut volume = Shape::Sphere(Sphere::new());
+        volume.set_material(Material::new().with_transparency(1.)
+            .with_medium(Medium::new(Color::new(0.5, 0.5, 0.5), BLACK)));
+        world.objects.push(volume);
+        world.fog = Some(Medium::new(Color::new(0.1, 0.1, 0.1), BLACK));
+
+        let expected = WHITE * (-1_f64).exp() * (-0.8_f64).exp();
+
+        let actual = world.transmittance_from(Tuple::point(0., 0., -3.),
+            LightPosition::Point(Tuple::point(0., 0., 7.)));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn thick_glass_absorbs_more_than_thin_glass() {
+        let world_with_ball = |radius: f64| {
+            let mut world = World { background: Background::Color(WHITE), ..World::new() };
+            let mut ball = Shape::Sphere(Sphere::new());
+            ball.set_transform(scale(radius, radius, radius));
+            ball.set_material(Material::new().with_transparency(1.).with_diffuse(0.).with_ambient(0.)
+                .with_specular(0.).with_absorption(Color::new(0.5, 0.25, 0.)));
+            world.objects.push(ball);
+
+            world
+        };
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected_thin = Color::new((-1_f64).exp(), (-0.5_f64).exp(), 1.);
+        let expected_thick = Color::new((-2_f64).exp(), (-1_f64).exp(), 1.);
+
+        let actual_thin = world_with_ball(1.).color_at(ray, DEFAULT_RECURSION);
+        let actual_thick = world_with_ball(2.).color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected_thin, actual_thin);
+        assert_eq!(expected_thick, actual_thick);
+    }
+
+    #[test]
+    fn absorbing_objects_dim_shadow_rays() {
+        let mut world = World::new();
+        let mut ball = Shape::Sphere(Sphere::new());
+        ball.set_material(Material::new().with_transparency(1.).with_absorption(Color::new(0.5, 0.5, 0.5)));
+        world.objects.push(ball);
+
+        let expected = WHITE * (-1_f64).exp();
+
+        let actual = world.transmittance_from(Tuple::point(0., 0., -3.),
+            LightPosition::Point(Tuple::point(0., 0., 7.)));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn fog_scatters_light_toward_eye() {
+        let mut world = World::new();
+        world.lights.push(Light::point_light(Tuple::point(0., 1., 0.), WHITE));
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        assert_eq!(BLACK, world.color_at(ray, DEFAULT_RECURSION));
+
+        world.fog = Some(Medium::new(BLACK, Color::new(0.1, 0.1, 0.1)));
+
+        assert!(world.color_at(ray, DEFAULT_RECURSION).red > 0.);
+    }
+
+    #[test]
+    fn directional_light_shadows_are_unbounded() {
+        let mut world: World = Default::default();
+        world.lights[0] = Light::Directional(DirectionalLight::new(Tuple::vector(1., -1., 1.), WHITE));
+
+        assert!(world.is_shadowed(Tuple::point(10., -10., 10.))[0]);
+        assert!(world.is_shadowed(Tuple::point(1000., -1000., 1000.))[0]);
+        assert!(!world.is_shadowed(Tuple::point(-2., 2., -2.))[0]);
+    }
+
+    #[test]
+    fn opaque_objects_block_all_light() {
+        let world: World = Default::default();
+
+        let expected = BLACK;
+
+        let actual = world.transmittance_from(Tuple::point(10., -10., 10.),
+            LightPosition::Point(Tuple::point(-10., 10., -10.)));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn transparent_objects_filter_light_once() {
+        let mut world = World::new();
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_color(Color::new(1., 0.5, 0.)).with_transparency(0.8));
+        world.objects.push(sphere);
+
+        let expected = Color::new(0.8, 0.4, 0.);
+
+        let actual = world.transmittance_from(Tuple::point(0., 0., -5.),
+            LightPosition::Point(Tuple::point(0., 0., 5.)));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn patterned_objects_filter_light_by_pattern_color() {
+        let mut world = World::new();
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_transparency(1.)
+            .with_pattern(Pattern::Striped(StripedPattern::new(Color::new(1., 0., 0.), Color::new(0., 0., 1.)))));
+        world.objects.push(sphere);
+
+        let expected_first_stripe = Color::new(1., 0., 0.);
+        let expected_second_stripe = Color::new(0., 0., 1.);
+
+        let actual_first_stripe = world.transmittance_from(Tuple::point(0.5, 0., -5.),
+            LightPosition::Point(Tuple::point(0.5, 0., 5.)));
+        let actual_second_stripe = world.transmittance_from(Tuple::point(-0.5, 0., -5.),
+            LightPosition::Point(Tuple::point(-0.5, 0., 5.)));
+
+        assert_eq!(expected_first_stripe, actual_first_stripe);
+        assert_eq!(expected_second_stripe, actual_second_stripe);
+    }
+
+    #[test]
+    fn filters_from_several_objects_multiply() {
+        let mut world = World::new();
+        let mut sphere1 = Shape::Sphere(Sphere::new());
+        sphere1.set_material(Material::new().with_transparency(0.5));
+        let mut sphere2 = Shape::Sphere(Sphere::new());
+        sphere2.set_material(Material::new().with_transparency(0.5));
+        sphere2.set_transform(translate(0., 0., 3.));
+        world.objects.push(sphere1);
+        world.objects.push(sphere2);
+
+        let expected = Color::new(0.25, 0.25, 0.25);
+
+        let actual = world.transmittance_from(Tuple::point(0., 0., -5.),
+            LightPosition::Point(Tuple::point(0., 0., 10.)));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn objects_beyond_light_do_not_filter() {
+        let mut world = World::new();
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_transparency(0.5));
+        world.objects.push(sphere);
+
+        let expected = WHITE;
+
+        let actual = world.transmittance_from(Tuple::point(0., 0., -5.),
+            LightPosition::Point(Tuple::point(0., 0., -3.)));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn objects_that_do_not_cast_shadows_let_light_through() {
+        let mut world: World = Default::default();
+        for object in world.objects.iter_mut() {
+            object.set_casts_shadow(false);
+        }
+
+        let expected = WHITE;
+
+        let actual = world.transmittance_from(Tuple::point(10., -10., 10.),
+            LightPosition::Point(Tuple::point(-10., 10., -10.)));
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn lights_without_shadows_are_never_shadowed() {
+        let mut world: World = Default::default();
+        world.lights[0] = world.lights[0].clone().with_casts_shadows(false);
+        let point = Tuple::point(10., -10., 10.);
+        let light = world.lights[0].clone();
+
+        assert!(!world.is_shadowed(point)[0]);
+        assert_eq!(WHITE, world.transmittance_at(&light, point));
+    }
+
+    #[test]
+    fn excluded_objects_receive_no_light() {
+        let mut world: World = Default::default();
+        let outer_id = world.objects[0].get_id();
+        world.lights[0] = world.lights[0].clone().with_exclude(vec![outer_id]);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = BLACK;
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn included_objects_are_lit_by_their_light_only() {
+        let mut world: World = Default::default();
+        let outer_id = world.objects[0].get_id();
+        let inner_id = world.objects[1].get_id();
+        let mut rim_light = Light::point_light(Tuple::point(10., 10., -10.), WHITE).with_include(vec![inner_id]);
+        rim_light.set_intensity(Color::new(0.5, 0.5, 0.5));
+        world.lights.push(rim_light);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = Color::new(0.38066, 0.47583, 0.2855);
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+        assert!(!world.lights[1].get_linking().illuminates(&[outer_id]));
+    }
+
+    #[test]
+    fn light_linking_applies_to_group_members() {
+        let mut group = Shape::Group(Group::new());
+        let mut sphere = Shape::Sphere(Sphere::new());
+        group.add_child(&mut sphere);
+        let group_id = group.get_id();
+        let mut world = World::new();
+        world.objects.push(group);
+        world.lights.push(Light::point_light(Tuple::point(0., 0., -10.), WHITE).with_exclude(vec![group_id]));
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = BLACK;
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn emissive_materials_glow_without_lights() {
+        let mut world = World::new();
+        let mut sphere = Shape::Sphere(Sphere::new());
+        sphere.set_material(Material::new().with_emission(Color::new(1.5, 0.5, 0.)));
+        world.objects.push(sphere);
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        let expected = Color::new(1.5, 0.5, 0.);
+
+        let actual = world.color_at(ray, DEFAULT_RECURSION);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn promoting_emissive_shapes_to_lights() {
+        let mut world = World::new();
+        let mut lamp = Shape::Sphere(Sphere::new());
+        lamp.set_material(Material::new().with_emission(WHITE));
+        lamp.set_transform(translate(0., 5., 0.));
+        // Members of a group share the group's material.
+        let mut group = Shape::Group(Group::new());
+        group.set_material(Material::new().with_emission(Color::new(0.5, 0.5, 0.5)));
+        let mut panel = Shape::Triangle(Triangle::new(Tuple::point(0., 1., 0.), Tuple::point(-1., 0., 0.),
+            Tuple::point(1., 0., 0.)));
+        group.add_child(&mut panel);
+        // Cubes cannot be sampled as lights.
+        let mut glowing_cube = Shape::Cube(Cube::new());
+        glowing_cube.set_material(Material::new().with_emission(WHITE));
+        world.objects.push(lamp);
+        world.objects.push(group);
+        world.objects.push(glowing_cube);
+        world.objects.push(Shape::Plane(Plane::new()));
+
+        world.promote_emissive_shapes(4);
+
+        assert_eq!(2, world.lights.len());
+        assert_eq!(WHITE, world.lights[0].get_intensity());
+        assert_eq!(Color::new(0.5, 0.5, 0.5), world.lights[1].get_intensity());
+    }
+
+    #[test]
+    fn emissive_shapes_light_the_scene_without_shadowing_themselves() {
+        let mut world = World::new();
+        let mut lamp = Shape::Sphere(Sphere::new());
+        lamp.set_material(Material::new().with_emission(WHITE));
+        lamp.set_transform(translate(0., 3., 0.) * scale(0.5, 0.5, 0.5));
+        let mut floor = Shape::Plane(Plane::new());
+        floor.set_material(Material::new().with_ambient(0.).with_specular(0.));
+        world.objects.push(lamp);
+        world.objects.push(floor);
+        world.promote_emissive_shapes(16);
+        let light = world.lights[0].clone();
+        let point = Tuple::point(0., 0.0001, 0.);
+
+        let expected = WHITE;
+
+        let actual = world.transmittance_at(&light, point);
+
+        assert_eq!(expected, actual);
+        assert!(world.color_at(Ray::new(Tuple::point(2., 1., 0.), Tuple::vector(0., -1., 0.)), DEFAULT_RECURSION).red > 0.);
+    }
+
     #[test]
     fn shade_hit_is_given_intersection_in_shadow() {
         let mut world = World::new();
@@ -484,6 +1419,26 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn dispersive_glass_bends_blue_more_than_red() {
+        let mut shape = Shape::Sphere(Sphere::new());
+        shape.set_material(Material::new().with_transparency(1.).with_refractive_index(1.5)
+            .with_dispersion(Dispersion::bk7()));
+        let refracted = |wavelength: f64| {
+            let ray = Ray::new(Tuple::point(0., 0.5, -5.), Tuple::vector(0., 0., 1.))
+                .with_wavelength(Some(wavelength));
+            let intersections = shape.intersect(ray);
+            let computations = intersections[0].prepare_computations(ray, intersections.clone());
+
+            World::refracted_direction(&computations).unwrap()
+        };
+
+        let blue = refracted(450.);
+        let red = refracted(650.);
+
+        assert!(blue.y < red.y);
+    }
+
     #[test]
     fn refracted_color_with_refracted_ray() {
         let mut world: World = Default::default();
@@ -533,7 +1488,8 @@ mod tests {
         let intersections = intersections!(Intersection::new(2_f64.sqrt(), floor));
         let computations = intersections[0].prepare_computations(ray, intersections.clone());
 
-        let expected = Color::new(0.93642, 0.68642, 0.68642);
+        // The ball is lit through the half transparent floor.
+        let expected = Color::new(1.12547, 0.68643, 0.68643);
 
         let actual = world.shade_hit(computations, DEFAULT_RECURSION);
 
@@ -564,7 +1520,8 @@ mod tests {
         let intersections = intersections!(Intersection::new(2_f64.sqrt(), floor));
         let computations = intersections[0].prepare_computations(ray, intersections.clone());
 
-        let expected = Color::new(0.93391, 0.69643, 0.69243);
+        // The ball is lit through the half transparent floor.
+        let expected = Color::new(1.11500, 0.69643, 0.69243);
 
         let actual = world.shade_hit(computations, DEFAULT_RECURSION);
 